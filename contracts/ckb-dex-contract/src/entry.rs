@@ -7,21 +7,29 @@ use core::result::Result;
 use ckb_std::{
   default_alloc,
   ckb_constants::Source,
-  high_level::load_witness_args,
 };
 
 use share::error::Error;
 use share::signature;
 
+mod action;
 mod order;
+mod settlement;
+
+use action::{load_action, Action};
 
 // Alloc 4K fast HEAP + 2M HEAP to receives PrefilledData
 default_alloc!(4 * 1024, 2048 * 1024, 64);
 
 pub fn main() -> Result<(), Error> {
-  return match load_witness_args(0, Source::GroupInput) {
-    Ok(_) => signature::validate(),
-    Err(_) => order::validate(),
+  // The first input of the group selects the action, which the owner signs for cancel and modify
+  return match load_action(0, Source::GroupInput)? {
+    Action::Fill(_) => order::validate(),
+    Action::Cancel => signature::validate(),
+    Action::Modify(_) => {
+      signature::validate()?;
+      order::validate_modify()
+    }
   };
 
 }
@@ -0,0 +1,74 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+  ckb_constants::Source,
+  ckb_types::{bytes::Bytes, prelude::*},
+  error::SysError,
+  high_level::load_witness_args,
+};
+
+use share::error::Error;
+
+use super::settlement::{read_u32, table_field, Settlement};
+
+// Every order input selects what happens to it in `WitnessArgs.output_type` of the witness at
+// the same index as the input, encoded as a molecule union:
+//
+// option SettlementOpt (Settlement);
+//
+// table Cancel {}
+//
+// table Modify {
+//     output_index: Uint32,  // the order with the new terms
+// }
+//
+// union OrderAction {
+//     SettlementOpt,  // fill, without settlement the order is settled into the output at the same index
+//     Cancel,         // the owner takes the order back
+//     Modify,         // the owner changes the terms of the order
+// }
+pub enum Action {
+  Fill(Option<Settlement>),
+  Cancel,
+  Modify(usize),
+}
+
+const ACTION_FILL: u32 = 0;
+const ACTION_CANCEL: u32 = 1;
+const ACTION_MODIFY: u32 = 2;
+
+impl Action {
+  pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+    let item_id = read_u32(data, 0).map_err(|_| Error::WrongAction)?;
+    let item = &data[4..];
+    match item_id {
+      ACTION_FILL if item.is_empty() => Ok(Action::Fill(None)),
+      ACTION_FILL => Ok(Action::Fill(Some(Settlement::from_slice(item)?))),
+      ACTION_CANCEL => {
+        if read_u32(item, 0)? as usize != item.len() {
+          return Err(Error::Encoding);
+        }
+        Ok(Action::Cancel)
+      }
+      ACTION_MODIFY => match table_field(item, 0)? {
+        Some(field) if field.len() == 4 => Ok(Action::Modify(read_u32(field, 0)? as usize)),
+        _ => Err(Error::Encoding),
+      },
+      _ => Err(Error::WrongAction),
+    }
+  }
+}
+
+pub fn load_action(index: usize, source: Source) -> Result<Action, Error> {
+  let witness_args = match load_witness_args(index, source) {
+    Ok(witness_args) => witness_args,
+    // The witness is absent, empty or not WitnessArgs
+    Err(SysError::IndexOutOfBound) | Err(SysError::Encoding) => return Err(Error::ActionMissing),
+    Err(err) => return Err(err.into()),
+  };
+  let action: Bytes = witness_args.output_type().to_opt().ok_or(Error::ActionMissing)?.unpack();
+  Action::from_slice(&action)
+}
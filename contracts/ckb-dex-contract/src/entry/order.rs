@@ -2,25 +2,99 @@
 use core::result::Result;
 
 // Import heap related library from `alloc`
+// https://doc.rust-lang.org/alloc/index.html
+use alloc::vec::Vec;
+
 // Import CKB syscalls and structures
 // https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
 use ckb_std::{
   ckb_constants::Source,
-  ckb_types::prelude::*,
+  ckb_types::{bytes::Bytes, prelude::*},
   error::SysError,
   high_level::{
-    load_cell_capacity, load_cell_data, load_transaction, load_input, load_cell_type_hash
+    load_cell_capacity, load_cell_data, load_transaction, load_input, load_cell_type_hash,
+    load_cell_lock_hash, load_header, load_cell_lock, load_script, QueryIter,
   },
 };
 
 use share::error::Error;
+use share::u256::U256;
+
+use super::action::{load_action, Action};
+use super::settlement::{Settlement, SIDE_MAKER, SIDE_TAKER};
 
-const FEE: f64 = 0.003;
-const ORDER_LEN: usize = 57;
+// fee = fee_rate / FEE_RATE_DECIMAL, orders without a fee rate pay 0.3%
+const DEFAULT_FEE_RATE: u16 = 30;
+const FEE_RATE_DECIMAL: u128 = 10_000;
 const SUDT_LEN: usize = 16;
+// v0 has no header: sudt_amount(u128) + dealt(u128) + undealt(u128) + price(u64) + order_type(u8)
+const ORDER_V0_LEN: usize = 57;
+// v1: sudt_amount(u128) + version(u8) + flags(u16) + dealt(u128) + undealt(u128) + price(u64) + order_type(u8)
+// + optional fields in the order of the flag bits
+const ORDER_V1_LEN: usize = 60;
+// The order region of v1 is everything after sudt_amount
+const ORDER_V1_REGION_LEN: usize = ORDER_V1_LEN - SUDT_LEN;
+// region_len(u32), the trailer of an order with udt extension data
+const REGION_LEN_LEN: usize = 4;
+const ORDER_V0: u8 = 0;
+const ORDER_V1: u8 = 1;
+// fee_rate(u16), in basis points
+const ORDER_FLAG_FEE_RATE: u16 = 1;
+// expiry_type(u8) + expiry(u64)
+const ORDER_FLAG_EXPIRY: u16 = 1 << 1;
+// owner_lock_hash([u8; 32]), the lock which receives the completed order
+const ORDER_FLAG_OWNER_LOCK: u16 = 1 << 2;
+// min_fill_amount(u128), the least sudt amount of a fill
+const ORDER_FLAG_MIN_FILL: u16 = 1 << 3;
+// The order may rest, but it can only be filled completely
+const ORDER_FLAG_ALL_OR_NONE: u16 = 1 << 4;
+// The order must be filled completely in one transaction before its expiry, otherwise it is killed,
+// i.e. returned to the owner by anyone, so it requires ORDER_FLAG_EXPIRY. A script can only prove that
+// a transaction is after a header, see `is_order_expired`, so the kill is up to whoever returns the
+// order: a matcher which leaves out the header deps can still fill it completely after its expiry.
+const ORDER_FLAG_FILL_OR_KILL: u16 = 1 << 5;
+// The order is never the taker of a matching
+const ORDER_FLAG_POST_ONLY: u16 = 1 << 6;
+// quote_type_hash([u8; 32]), the order sells its sudt for the quote udt instead of capacity.
+// A cell holds one udt only, so the order is always a sell order, and buying sudt A with
+// sudt B is a sell order of sudt B quoted in sudt A.
+const ORDER_FLAG_QUOTE_UDT: u16 = 1 << 7;
+// price_bound(u64), the worst acceptable price of a market order, which is the max price of a buy
+// and the min price of a sell. A market order has no limit price, so its price is zero.
+const ORDER_FLAG_MARKET: u16 = 1 << 8;
+// order_id([u8; 32]), blake2b(first input outpoint || output index as u64) of the transaction which
+// creates the order, like type id. A lock script doesn't run when its cell is created, so indexers
+// check the id against the creating transaction, and the script keeps it across fills.
+const ORDER_FLAG_ORDER_ID: u16 = 1 << 9;
+// oracle_type_hash([u8; 32]) + trigger_price(u64) + trigger_direction(u8), the order can only be filled
+// once the price of the oracle cell crosses the trigger price, e.g. stop-loss and take-profit orders
+const ORDER_FLAG_TRIGGER: u16 = 1 << 10;
+// The udt keeps its extension data, e.g. xudt_data of xUDT, right after the amount, so the order region
+// (version, flags, ..., optional fields) moves to the end and is delimited by its length. An order cell is
+// sudt_amount(u128) + udt extension + order region + region_len(u32), and the completed order is
+// sudt_amount(u128) + udt extension again.
+const ORDER_FLAG_UDT_EXTENSION: u16 = 1 << 11;
+// matcher_config_type_hash([u8; 32]), only the matchers listed by the config cell can fill the order
+const ORDER_FLAG_MATCHER_SET: u16 = 1 << 12;
+// Flag bits understood by this script
+const ORDER_V1_FLAGS: u16 = ORDER_FLAG_FEE_RATE | ORDER_FLAG_EXPIRY | ORDER_FLAG_OWNER_LOCK | ORDER_FLAG_MIN_FILL
+  | ORDER_FLAG_ALL_OR_NONE | ORDER_FLAG_FILL_OR_KILL | ORDER_FLAG_POST_ONLY | ORDER_FLAG_QUOTE_UDT | ORDER_FLAG_MARKET
+  | ORDER_FLAG_ORDER_ID | ORDER_FLAG_TRIGGER | ORDER_FLAG_UDT_EXTENSION | ORDER_FLAG_MATCHER_SET;
+const EXPIRY_BY_BLOCK_NUMBER: u8 = 0;
+// header timestamp in milliseconds
+const EXPIRY_BY_TIMESTAMP: u8 = 1;
+// triggered when the oracle price <= trigger_price
+const TRIGGER_BELOW: u8 = 0;
+// triggered when the oracle price >= trigger_price
+const TRIGGER_ABOVE: u8 = 1;
+// lock args: pubkey_hash(20 bytes) of the owner + the udt type hash(32 bytes) which the market accepts.
+// Orders of the bare pubkey_hash were placed before markets committed to their udt, so they keep being
+// filled on any udt as before, and markets which need the whitelist only take orders of the full args.
+// Matchers must check the udt of a bare pubkey_hash order themselves.
+const PUBKEY_HASH_LEN: usize = 20;
+const MARKET_ARGS_LEN: usize = 52;
 // real price * 10 ^ 10 = cell price data
-const PRICE_PARAM: f64 = 10000000000.0;
-const PRECISION_NUMBER: f64 = 0.0001;
+const PRICE_PARAM: u128 = 10_000_000_000;
 
 struct OrderData {
   sudt_amount: u128,
@@ -28,6 +102,23 @@ struct OrderData {
   undealt_amount: u128,
   price: u64,
   order_type: u8,
+  version: u8,
+  flags: u16,
+  fee_rate: u16,
+  expiry_type: u8,
+  expiry: u64,
+  owner_lock_hash: [u8; 32],
+  min_fill_amount: u128,
+  quote_type_hash: [u8; 32],
+  price_bound: u64,
+  order_id: [u8; 32],
+  oracle_type_hash: [u8; 32],
+  trigger_price: u64,
+  trigger_direction: u8,
+  udt_extension: Bytes,
+  matcher_config_type_hash: [u8; 32],
+  // false for the plain sudt cell of a completed order
+  is_order: bool,
 }
 
 fn _init_order_data() -> OrderData {
@@ -37,96 +128,530 @@ fn _init_order_data() -> OrderData {
     undealt_amount: 0u128,
     price: 0u64,
     order_type: 0u8,
+    version: ORDER_V0,
+    flags: 0u16,
+    fee_rate: DEFAULT_FEE_RATE,
+    expiry_type: EXPIRY_BY_BLOCK_NUMBER,
+    expiry: 0u64,
+    owner_lock_hash: [0u8; 32],
+    min_fill_amount: 0u128,
+    quote_type_hash: [0u8; 32],
+    price_bound: 0u64,
+    order_id: [0u8; 32],
+    oracle_type_hash: [0u8; 32],
+    trigger_price: 0u64,
+    trigger_direction: TRIGGER_BELOW,
+    udt_extension: Bytes::new(),
+    matcher_config_type_hash: [0u8; 32],
+    is_order: false,
+  }
+}
+
+// dealt(u128) + undealt(u128) + price(u64) + order_type(u8), shared by all versions
+fn parse_order_body(data: &[u8], order: &mut OrderData) {
+  let mut dealt_amount_buf = [0u8; 16];
+  let mut undealt_amount_buf = [0u8; 16];
+  let mut price_buf = [0u8; 8];
+
+  dealt_amount_buf.copy_from_slice(&data[0..16]);
+  undealt_amount_buf.copy_from_slice(&data[16..32]);
+  price_buf.copy_from_slice(&data[32..40]);
+
+  order.dealt_amount = u128::from_le_bytes(dealt_amount_buf);
+  order.undealt_amount = u128::from_le_bytes(undealt_amount_buf);
+  order.price = u64::from_le_bytes(price_buf);
+  order.order_type = data[40];
+}
+
+fn read_order_field<'a>(data: &'a [u8], offset: &mut usize, len: usize) -> Result<&'a [u8], Error> {
+  if data.len() < *offset + len {
+    return Err(Error::WrongDataLengthOrFormat);
+  }
+  let field = &data[*offset..*offset + len];
+  *offset += len;
+  Ok(field)
+}
+
+// The order region of v1: version(u8) + flags(u16) + dealt(u128) + undealt(u128) + price(u64) + order_type(u8)
+// + optional fields
+fn parse_order_v1(data: &[u8], order: &mut OrderData) -> Result<(), Error> {
+  let mut flags_buf = [0u8; 2];
+  flags_buf.copy_from_slice(&data[1..3]);
+  let flags = u16::from_le_bytes(flags_buf);
+  if flags & !ORDER_V1_FLAGS != 0 {
+    return Err(Error::WrongOrderFlags);
+  }
+  if flags & ORDER_FLAG_FILL_OR_KILL != 0 && flags & ORDER_FLAG_EXPIRY == 0 {
+    return Err(Error::WrongOrderFlags);
+  }
+  // A market order takes liquidity, it is never a maker
+  if flags & ORDER_FLAG_MARKET != 0 && flags & ORDER_FLAG_POST_ONLY != 0 {
+    return Err(Error::WrongOrderFlags);
+  }
+
+  order.version = ORDER_V1;
+  order.flags = flags;
+  parse_order_body(&data[3..ORDER_V1_REGION_LEN], order);
+
+  let mut offset = ORDER_V1_REGION_LEN;
+  if flags & ORDER_FLAG_FEE_RATE != 0 {
+    let mut fee_rate_buf = [0u8; 2];
+    fee_rate_buf.copy_from_slice(read_order_field(data, &mut offset, 2)?);
+    order.fee_rate = u16::from_le_bytes(fee_rate_buf);
+    if order.fee_rate as u128 > FEE_RATE_DECIMAL {
+      return Err(Error::WrongFeeRate);
+    }
+  }
+  if flags & ORDER_FLAG_EXPIRY != 0 {
+    let field = read_order_field(data, &mut offset, 9)?;
+    let mut expiry_buf = [0u8; 8];
+    expiry_buf.copy_from_slice(&field[1..9]);
+    order.expiry_type = field[0];
+    order.expiry = u64::from_le_bytes(expiry_buf);
+    if order.expiry_type != EXPIRY_BY_BLOCK_NUMBER && order.expiry_type != EXPIRY_BY_TIMESTAMP {
+      return Err(Error::WrongExpiry);
+    }
+  }
+  if flags & ORDER_FLAG_OWNER_LOCK != 0 {
+    order.owner_lock_hash.copy_from_slice(read_order_field(data, &mut offset, 32)?);
+  }
+  if flags & ORDER_FLAG_MIN_FILL != 0 {
+    let mut min_fill_amount_buf = [0u8; 16];
+    min_fill_amount_buf.copy_from_slice(read_order_field(data, &mut offset, 16)?);
+    order.min_fill_amount = u128::from_le_bytes(min_fill_amount_buf);
   }
+  if flags & ORDER_FLAG_QUOTE_UDT != 0 {
+    order.quote_type_hash.copy_from_slice(read_order_field(data, &mut offset, 32)?);
+  }
+  if flags & ORDER_FLAG_MARKET != 0 {
+    let mut price_bound_buf = [0u8; 8];
+    price_bound_buf.copy_from_slice(read_order_field(data, &mut offset, 8)?);
+    order.price_bound = u64::from_le_bytes(price_bound_buf);
+  }
+  if flags & ORDER_FLAG_ORDER_ID != 0 {
+    order.order_id.copy_from_slice(read_order_field(data, &mut offset, 32)?);
+  }
+  if flags & ORDER_FLAG_TRIGGER != 0 {
+    let field = read_order_field(data, &mut offset, 41)?;
+    let mut trigger_price_buf = [0u8; 8];
+    trigger_price_buf.copy_from_slice(&field[32..40]);
+    order.oracle_type_hash.copy_from_slice(&field[0..32]);
+    order.trigger_price = u64::from_le_bytes(trigger_price_buf);
+    order.trigger_direction = field[40];
+    if order.trigger_direction != TRIGGER_BELOW && order.trigger_direction != TRIGGER_ABOVE {
+      return Err(Error::WrongTrigger);
+    }
+  }
+  if flags & ORDER_FLAG_MATCHER_SET != 0 {
+    order.matcher_config_type_hash.copy_from_slice(read_order_field(data, &mut offset, 32)?);
+  }
+  if offset != data.len() {
+    return Err(Error::WrongDataLengthOrFormat);
+  }
+  Ok(())
 }
 
+// The start of the order region of an order with udt extension data, whose region_len trailer points
+// to a v1 region with ORDER_FLAG_UDT_EXTENSION
+fn udt_extension_region(data: &[u8]) -> Option<usize> {
+  if data.len() < ORDER_V1_LEN + REGION_LEN_LEN {
+    return None;
+  }
+  let region_end = data.len() - REGION_LEN_LEN;
+  let mut region_len_buf = [0u8; 4];
+  region_len_buf.copy_from_slice(&data[region_end..]);
+  let region_len = u32::from_le_bytes(region_len_buf) as usize;
+  if region_len < ORDER_V1_REGION_LEN || region_len > region_end - SUDT_LEN {
+    return None;
+  }
+  let region_start = region_end - region_len;
+  let flags = u16::from_le_bytes([data[region_start + 1], data[region_start + 2]]);
+  if data[region_start] != ORDER_V1 || flags & ORDER_FLAG_UDT_EXTENSION == 0 {
+    return None;
+  }
+  Some(region_start)
+}
 
 fn parse_order_data(data: &[u8]) -> Result<OrderData, Error> {
-  // sudt_amount(u128) or sudt_amount(u128) + dealt(u128) + undealt(u128) + price(u64) + order_type(u8)
-  if data.len() != SUDT_LEN && data.len() != ORDER_LEN {
+  // sudt_amount(u128) or v0 order or versioned order whose version follows sudt_amount
+  if data.len() < SUDT_LEN {
     return Err(Error::WrongDataLengthOrFormat);
   }
+  let mut order = _init_order_data();
   let mut sudt_amount_buf = [0u8; 16];
-  let mut dealt_amount_buf = [0u8; 16];
-  let mut undealt_amount_buf = [0u8; 16];
+  sudt_amount_buf.copy_from_slice(&data[0..16]);
+  order.sudt_amount = u128::from_le_bytes(sudt_amount_buf);
+
+  if let Some(region_start) = udt_extension_region(data) {
+    parse_order_v1(&data[region_start..data.len() - REGION_LEN_LEN], &mut order)?;
+    order.udt_extension = Bytes::from(data[SUDT_LEN..region_start].to_vec());
+    order.is_order = true;
+    return Ok(order);
+  }
+  match data.len() {
+    SUDT_LEN => return Ok(order),
+    // Versioned orders are never shorter than ORDER_V1_LEN, so they can not be mistaken for v0
+    ORDER_V0_LEN => parse_order_body(&data[SUDT_LEN..], &mut order),
+    len if len >= ORDER_V1_LEN => match data[SUDT_LEN] {
+      ORDER_V1 => {
+        parse_order_v1(&data[SUDT_LEN..], &mut order)?;
+        // The order region of an order with udt extension data is always at the end
+        if order.flags & ORDER_FLAG_UDT_EXTENSION != 0 {
+          return Err(Error::WrongOrderFlags);
+        }
+      }
+      _ => return Err(Error::WrongOrderVersion),
+    },
+    _ => return Err(Error::WrongDataLengthOrFormat),
+  }
+  order.is_order = true;
+  Ok(order)
+}
+
+fn load_data(index: usize, source: Source) -> Result<Vec<u8>, Error> {
+  match load_cell_data(index, source) {
+      Ok(data) => Ok(data),
+      Err(SysError::IndexOutOfBound) => Err(Error::IndexOutOfBound),
+      Err(err) => Err(err.into()),
+  }
+}
+
+fn parse_cell_data(index: usize, source: Source) -> Result<OrderData, Error> {
+  parse_order_data(&load_data(index, source)?)
+}
+
+// An output is either the remaining order or the completed order, which is a plain udt cell
+// carrying the udt extension of the input order
+fn parse_output_data(output_index: usize, input_order: &OrderData) -> Result<OrderData, Error> {
+  let data = load_data(output_index, Source::Output)?;
+  let extension = &input_order.udt_extension;
+  if !extension.is_empty() && data.len() == SUDT_LEN + extension.len() && data[SUDT_LEN..] == extension[..] {
+    let mut order = parse_order_data(&data[..SUDT_LEN])?;
+    order.udt_extension = extension.clone();
+    return Ok(order);
+  }
+  parse_order_data(&data)
+}
+
+// CKB can only prove that a transaction is committed after its header deps, so an order
+// is regarded as expired once any of the header deps reaches its expiry.
+fn is_order_expired(order: &OrderData) -> bool {
+  if order.flags & ORDER_FLAG_EXPIRY == 0 {
+    return false;
+  }
+  QueryIter::new(load_header, Source::HeaderDep).any(|header| {
+    let raw_header = header.raw();
+    let current: u64 = match order.expiry_type {
+      EXPIRY_BY_BLOCK_NUMBER => raw_header.number().unpack(),
+      _ => raw_header.timestamp().unpack(),
+    };
+    current >= order.expiry
+  })
+}
+
+// The lock which receives a completed or returned order, the order lock itself by default
+fn owner_lock_hash(input_index: usize, order: &OrderData) -> Result<[u8; 32], Error> {
+  if order.flags & ORDER_FLAG_OWNER_LOCK != 0 {
+    Ok(order.owner_lock_hash)
+  } else {
+    Ok(load_cell_lock_hash(input_index, Source::Input)?)
+  }
+}
+
+// Anyone can return an expired order to its owner without signature: the output at the
+// matched index goes to the owner lock with the capacity and the sudt amount, and is no longer an order.
+fn validate_expired_order(input_index: usize, output_index: usize, input_order: &OrderData) -> Result<(), Error> {
+  if owner_lock_hash(input_index, input_order)? != load_cell_lock_hash(output_index, Source::Output)? {
+    return Err(Error::WrongOwnerLock);
+  }
+  if load_cell_capacity(input_index, Source::Input)? > load_cell_capacity(output_index, Source::Output)? {
+    return Err(Error::WrongDiffCapacity);
+  }
+  // Any fill of an expired order is refused
+  let output_order = parse_output_data(output_index, input_order)?;
+  if output_order.is_order
+    || output_order.sudt_amount != input_order.sudt_amount
+    || output_order.udt_extension != input_order.udt_extension
+  {
+    return Err(Error::OrderExpired);
+  }
+  Ok(())
+}
+
+// The udt type hash committed by the lock args, orders on any other asset are refused.
+// None for an order of the bare pubkey_hash.
+fn load_market_udt_type_hash() -> Result<Option<[u8; 32]>, Error> {
+  let args: Bytes = load_script()?.args().unpack();
+  match args.len() {
+    PUBKEY_HASH_LEN => Ok(None),
+    MARKET_ARGS_LEN => {
+      let mut type_hash = [0u8; 32];
+      type_hash.copy_from_slice(&args[PUBKEY_HASH_LEN..]);
+      Ok(Some(type_hash))
+    }
+    _ => Err(Error::Encoding),
+  }
+}
+
+// The oracle cell is a cell dep whose data starts with the reference price(u64), in the unit of the order price
+fn load_oracle_price(oracle_type_hash: &[u8; 32]) -> Result<u64, Error> {
+  let oracle_index = QueryIter::new(load_cell_type_hash, Source::CellDep)
+    .position(|type_hash| type_hash.as_ref() == Some(oracle_type_hash))
+    .ok_or(Error::OracleNotFound)?;
+  let data = load_cell_data(oracle_index, Source::CellDep)?;
+  if data.len() < 8 {
+    return Err(Error::WrongDataLengthOrFormat);
+  }
   let mut price_buf = [0u8; 8];
-  let mut order_type_buf = [0u8; 1];
+  price_buf.copy_from_slice(&data[0..8]);
+  Ok(u64::from_le_bytes(price_buf))
+}
 
-  sudt_amount_buf.copy_from_slice(&data[0..16]);
-  if data.len() == ORDER_LEN {
-    dealt_amount_buf.copy_from_slice(&data[16..32]);
-    undealt_amount_buf.copy_from_slice(&data[32..48]);
-    price_buf.copy_from_slice(&data[48..56]);
-    order_type_buf.copy_from_slice(&data[56..57]);
-  }
-  Ok(OrderData {
-    sudt_amount: u128::from_le_bytes(sudt_amount_buf),
-    dealt_amount: u128::from_le_bytes(dealt_amount_buf),
-    undealt_amount: u128::from_le_bytes(undealt_amount_buf),
-    price: u64::from_le_bytes(price_buf),
-    order_type: u8::from_le_bytes(order_type_buf),
+fn is_order_triggered(order: &OrderData) -> Result<bool, Error> {
+  if order.flags & ORDER_FLAG_TRIGGER == 0 {
+    return Ok(true);
+  }
+  let oracle_price = load_oracle_price(&order.oracle_type_hash)?;
+  Ok(match order.trigger_direction {
+    TRIGGER_BELOW => oracle_price <= order.trigger_price,
+    _ => oracle_price >= order.trigger_price,
   })
 }
 
-fn parse_cell_data(index: usize, source: Source) -> Result<OrderData, Error> {
-  let data = match load_cell_data(index, source) {
-      Ok(data) => data,
-      Err(SysError::IndexOutOfBound) => return Err(Error::IndexOutOfBound),
-      Err(err) => return Err(err.into()),
-  };
-  return match data.len() {
-    ORDER_LEN => {
-      let mut data_buf = [0u8; ORDER_LEN];
-      data_buf.copy_from_slice(&data);
-      Ok(parse_order_data(&data_buf)?)
-    }
-    SUDT_LEN => {
-      let mut data_buf = [0u8; SUDT_LEN];
-      data_buf.copy_from_slice(&data);
-      Ok(parse_order_data(&data_buf)?)
-    }
-    _ => Err(Error::WrongDataLengthOrFormat),
-  };
+// The matcher config cell is a cell dep whose data is the list of the lock hashes([u8; 32]) of
+// the authorised matchers, and one of them must unlock an input of the fill transaction
+fn check_matcher(input_index: usize) -> Result<(), Error> {
+  let order = parse_cell_data(input_index, Source::Input)?;
+  // Anyone can return an expired order to its owner
+  if order.flags & ORDER_FLAG_MATCHER_SET == 0 || is_order_expired(&order) {
+    return Ok(());
+  }
+  let config_index = QueryIter::new(load_cell_type_hash, Source::CellDep)
+    .position(|type_hash| type_hash == Some(order.matcher_config_type_hash))
+    .ok_or(Error::MatcherConfigNotFound)?;
+  let matchers = load_data(config_index, Source::CellDep)?;
+  if matchers.len() % 32 != 0 {
+    return Err(Error::WrongMatcherConfig);
+  }
+  let authorised = QueryIter::new(load_cell_lock_hash, Source::Input)
+    .any(|lock_hash| matchers.chunks(32).any(|matcher| matcher == &lock_hash[..]));
+  if !authorised {
+    return Err(Error::MatcherNotAuthorized);
+  }
+  Ok(())
+}
+
+// A fill deals min_fill_amount at least unless it completes the order, and never leaves
+// a dust order whose undealt amount is less than min_fill_amount. All-or-none and fill-or-kill
+// orders are never left partially filled.
+fn check_fill_amount(input_order: &OrderData, output_order: &OrderData, diff_undealt_amount: u128) -> Result<(), Error> {
+  if input_order.flags & (ORDER_FLAG_ALL_OR_NONE | ORDER_FLAG_FILL_OR_KILL) != 0
+    && (output_order.is_order || output_order.undealt_amount != 0)
+  {
+    return Err(Error::OrderNotFullyFilled);
+  }
+  if diff_undealt_amount < input_order.min_fill_amount && diff_undealt_amount != input_order.undealt_amount {
+    return Err(Error::FillAmountTooSmall);
+  }
+  if output_order.undealt_amount != 0 && output_order.undealt_amount < input_order.min_fill_amount {
+    return Err(Error::RemainingAmountTooSmall);
+  }
+  Ok(())
+}
+
+// The udt amount of any udt cell, whatever extension data follows it
+fn load_udt_amount(index: usize, source: Source) -> Result<u128, Error> {
+  let data = load_data(index, source)?;
+  if data.len() < SUDT_LEN {
+    return Err(Error::WrongDataLengthOrFormat);
+  }
+  let mut amount_buf = [0u8; 16];
+  amount_buf.copy_from_slice(&data[0..SUDT_LEN]);
+  Ok(u128::from_le_bytes(amount_buf))
+}
+
+// The quote udt amount received by a sudt/sudt order, which is paid into a new cell of the owner
+fn load_quote_amount(input_index: usize, order: &OrderData, matched: &MatchedOutputs) -> Result<u128, Error> {
+  let quote_output_index = matched.quote_output_index.ok_or(Error::ItemMissing)?;
+  match load_cell_type_hash(quote_output_index, Source::Output)? {
+    Some(type_hash) if type_hash == order.quote_type_hash => {}
+    _ => return Err(Error::QuoteTypeHashNotSame),
+  }
+  if load_cell_lock_hash(quote_output_index, Source::Output)? != owner_lock_hash(input_index, order)? {
+    return Err(Error::WrongOwnerLock);
+  }
+  load_udt_amount(quote_output_index, Source::Output)
+}
+
+// A post-only order rests on the book, so the orders it is matched with must take it: another order
+// of the same pair on the other side is filled in the transaction and settled as the taker
+fn check_taker_found(input_index: usize, order: &OrderData, inputs_count: usize, outputs_count: usize) -> Result<(), Error> {
+  let type_hash = load_cell_type_hash(input_index, Source::Input)?;
+  for index in 0..inputs_count {
+    if index == input_index || !is_filled_order(index)? {
+      continue;
+    }
+    if load_matched_outputs(index, inputs_count, outputs_count)?.side != Some(SIDE_TAKER) {
+      continue;
+    }
+    let other_order = parse_cell_data(index, Source::Input)?;
+    let other_type_hash = load_cell_type_hash(index, Source::Input)?;
+    let is_counterparty = if order.flags & ORDER_FLAG_QUOTE_UDT != 0 {
+      // The other side sells the quote udt for the udt of the order
+      other_order.flags & ORDER_FLAG_QUOTE_UDT != 0
+        && other_type_hash == Some(order.quote_type_hash)
+        && type_hash == Some(other_order.quote_type_hash)
+    } else {
+      other_order.flags & ORDER_FLAG_QUOTE_UDT == 0
+        && other_type_hash == type_hash
+        && other_order.order_type != order.order_type
+    };
+    if other_order.is_order && is_counterparty {
+      return Ok(());
+    }
+  }
+  Err(Error::TakerNotFound)
 }
 
-fn validate_order_cells(index: usize) -> Result<(), Error> {
-  let input_type_hash = match load_cell_type_hash(index, Source::Input) {
+fn validate_order_cells(input_index: usize, matched: &MatchedOutputs, inputs_count: usize, outputs_count: usize) -> Result<(), Error> {
+  let output_index = matched.output_index;
+  let input_type_hash = match load_cell_type_hash(input_index, Source::Input) {
     Ok(hash) => hash,
     Err(err) => return Err(err.into())
   };
-  let output_type_hash = match load_cell_type_hash(index, Source::Output) {
+  let output_type_hash = match load_cell_type_hash(output_index, Source::Output) {
     Ok(hash) => hash,
     Err(err) => return Err(err.into())
   };
   if input_type_hash != output_type_hash {
     return Err(Error::TypeHashNotSame);
   }
-  let input_capacity = load_cell_capacity(index, Source::Input)?;
-  let output_capacity = load_cell_capacity(index, Source::Output)?;
-  let input_order = parse_cell_data(index, Source::Input)?;
-  let output_order = parse_cell_data(index, Source::Output)?;
+  if let Some(market_type_hash) = load_market_udt_type_hash()? {
+    if input_type_hash != Some(market_type_hash) {
+      return Err(Error::UDTNotAccepted);
+    }
+  }
+  let input_order = parse_cell_data(input_index, Source::Input)?;
+  if is_order_expired(&input_order) {
+    return validate_expired_order(input_index, output_index, &input_order);
+  }
+  if input_order.flags & ORDER_FLAG_POST_ONLY != 0 {
+    if matched.side != Some(SIDE_MAKER) {
+      return Err(Error::PostOnlyOrderNotMaker);
+    }
+    check_taker_found(input_index, &input_order, inputs_count, outputs_count)?;
+  }
+  if !is_order_triggered(&input_order)? {
+    return Err(Error::OrderNotTriggered);
+  }
+
+  let input_capacity = load_cell_capacity(input_index, Source::Input)?;
+  let output_capacity = load_cell_capacity(output_index, Source::Output)?;
+  let output_order = parse_output_data(output_index, &input_order)?;
+  if input_order.udt_extension != output_order.udt_extension {
+    return Err(Error::WrongUDTExtension);
+  }
+
+  // The remaining order stays with the order lock, and the completed order goes to its owner
+  let expected_lock_hash = if output_order.is_order {
+    load_cell_lock_hash(input_index, Source::Input)?
+  } else {
+    owner_lock_hash(input_index, &input_order)?
+  };
+  if load_cell_lock_hash(output_index, Source::Output)? != expected_lock_hash {
+    return Err(Error::WrongOwnerLock);
+  }
 
   if input_order.undealt_amount == 0 {
     return Err(Error::WrongSUDTInputAmount);
   }
-  if input_order.price == 0 {
+  // A market order is filled at its price bound at worst
+  let order_price = if input_order.flags & ORDER_FLAG_MARKET != 0 {
+    if input_order.price != 0 {
+      return Err(Error::WrongOrderPrice);
+    }
+    input_order.price_bound
+  } else {
+    input_order.price
+  };
+  if order_price == 0 {
     return Err(Error::OrderPriceNotZero);
   }
+  // In a batch auction the order is settled at the clearing price, which must satisfy its limit.
+  // The batch only conserves the udt and capacity of the order cells, so a quote udt order, which
+  // is paid in a quote output, can't take part in it
+  let order_price = match matched.clearing_price {
+    Some(_) if input_order.flags & ORDER_FLAG_QUOTE_UDT != 0 => return Err(Error::WrongClearingPrice),
+    Some(clearing_price) => {
+      let satisfied = if input_order.order_type == 0 {
+        clearing_price <= order_price
+      } else {
+        clearing_price >= order_price
+      };
+      if clearing_price == 0 || !satisfied {
+        return Err(Error::WrongClearingPrice);
+      }
+      clearing_price
+    }
+    None => order_price,
+  };
 
-  if output_order.dealt_amount != 0 {
+  if output_order.is_order {
     if input_order.order_type != output_order.order_type {
       return Err(Error::WrongOrderType);
     }
 
+    // The remaining order keeps its format and terms
+    if input_order.version != output_order.version {
+      return Err(Error::WrongOrderVersion);
+    }
+    if input_order.flags != output_order.flags {
+      return Err(Error::WrongOrderFlags);
+    }
+    if input_order.price != output_order.price || input_order.price_bound != output_order.price_bound {
+      return Err(Error::WrongOrderPrice);
+    }
+    if input_order.fee_rate != output_order.fee_rate {
+      return Err(Error::WrongFeeRate);
+    }
+    if input_order.expiry_type != output_order.expiry_type || input_order.expiry != output_order.expiry {
+      return Err(Error::WrongExpiry);
+    }
+    if input_order.owner_lock_hash != output_order.owner_lock_hash {
+      return Err(Error::WrongOwnerLock);
+    }
+    if input_order.min_fill_amount != output_order.min_fill_amount {
+      return Err(Error::WrongMinFillAmount);
+    }
+    if input_order.quote_type_hash != output_order.quote_type_hash {
+      return Err(Error::QuoteTypeHashNotSame);
+    }
+    if input_order.order_id != output_order.order_id {
+      return Err(Error::WrongOrderId);
+    }
+    if input_order.matcher_config_type_hash != output_order.matcher_config_type_hash {
+      return Err(Error::WrongMatcherConfig);
+    }
+    if input_order.oracle_type_hash != output_order.oracle_type_hash
+      || input_order.trigger_price != output_order.trigger_price
+      || input_order.trigger_direction != output_order.trigger_direction
+    {
+      return Err(Error::WrongTrigger);
+    }
+
     if input_order.dealt_amount > output_order.dealt_amount {
       return Err(Error::WrongSUDTDiffAmount);
     }
   }
 
-  let order_price: f64 = input_order.price as f64 / PRICE_PARAM;
- 
+  let order_price = order_price as u128;
+  let fee_rate = input_order.fee_rate as u128;
+
   // Buy SUDT
   if input_order.order_type == 0 {
+    if input_order.flags & ORDER_FLAG_QUOTE_UDT != 0 {
+      return Err(Error::WrongOrderType);
+    }
     if input_capacity < output_capacity {
       return Err(Error::WrongDiffCapacity);
     }
@@ -134,24 +659,28 @@ fn validate_order_cells(index: usize) -> Result<(), Error> {
       return Err(Error::WrongSUDTDiffAmount);
     }
 
-    let diff_undealt_amount = (input_order.undealt_amount - output_order.undealt_amount) as f64;
+    let diff_undealt_amount = input_order.undealt_amount - output_order.undealt_amount;
+    check_fill_amount(&input_order, &output_order, diff_undealt_amount)?;
 
-    if output_order.dealt_amount != 0 {
-      let diff_dealt_amount = (output_order.dealt_amount - input_order.dealt_amount) as f64;
+    if output_order.is_order {
+      let diff_dealt_amount = output_order.dealt_amount - input_order.dealt_amount;
 
       if diff_dealt_amount != diff_undealt_amount {
         return Err(Error::WrongSUDTDiffAmount);
       }
     }
 
-    let diff_capacity = (input_capacity - output_capacity) as f64;
-    let diff_sudt_amount = (output_order.sudt_amount - input_order.sudt_amount) as f64;
-    
+    let diff_capacity = input_capacity - output_capacity;
+    let diff_sudt_amount = output_order.sudt_amount - input_order.sudt_amount;
+
     if diff_sudt_amount != diff_undealt_amount {
       return Err(Error::WrongSUDTDiffAmount);
     }
 
-    if diff_undealt_amount * (1.0 + FEE) * order_price + PRECISION_NUMBER < diff_capacity {
+    // The buyer pays at most diff_undealt_amount * (1 + fee) * price, rounded down in favour of the buyer
+    let max_paid_capacity = U256::mul(diff_undealt_amount, order_price * (FEE_RATE_DECIMAL + fee_rate))
+      .div_floor(PRICE_PARAM * FEE_RATE_DECIMAL);
+    if U256::from(diff_capacity) > max_paid_capacity {
       return Err(Error::WrongSwapAmount);
     }
   } else if input_order.order_type == 1 {
@@ -164,25 +693,36 @@ fn validate_order_cells(index: usize) -> Result<(), Error> {
       return Err(Error::WrongSUDTDiffAmount);
     }
 
-    let diff_undealt_amount = (input_order.undealt_amount - output_order.undealt_amount) as f64;
+    let diff_undealt_amount = input_order.undealt_amount - output_order.undealt_amount;
+    check_fill_amount(&input_order, &output_order, diff_undealt_amount)?;
 
-    if output_order.dealt_amount != 0 {
-      let diff_dealt_amount = (output_order.dealt_amount - input_order.dealt_amount) as f64;
+    if output_order.is_order {
+      let diff_dealt_amount = output_order.dealt_amount - input_order.dealt_amount;
 
       if diff_dealt_amount != diff_undealt_amount {
         return Err(Error::WrongSUDTDiffAmount);
       }
     }
 
-    let diff_capacity = (output_capacity - input_capacity) as f64;
-    let diff_sudt_amount = (input_order.sudt_amount - output_order.sudt_amount) as f64;
-    
-    // Floating point numbers have precision errors
-    if diff_sudt_amount - diff_undealt_amount * (1.0 + FEE) > PRECISION_NUMBER {
+    let received_amount = if input_order.flags & ORDER_FLAG_QUOTE_UDT != 0 {
+      load_quote_amount(input_index, &input_order, matched)?
+    } else {
+      (output_capacity - input_capacity) as u128
+    };
+    let diff_sudt_amount = input_order.sudt_amount - output_order.sudt_amount;
+
+    // The seller spends at most diff_undealt_amount * (1 + fee) sudt, rounded down in favour of the seller
+    let max_spent_sudt_amount =
+      U256::mul(diff_undealt_amount, FEE_RATE_DECIMAL + fee_rate).div_floor(FEE_RATE_DECIMAL);
+    if U256::from(diff_sudt_amount) > max_spent_sudt_amount {
       return Err(Error::WrongSUDTDiffAmount);
     }
 
-    if diff_capacity * (1.0 + FEE) + PRECISION_NUMBER < diff_sudt_amount * order_price {
+    // The seller receives at least diff_sudt_amount * price / (1 + fee) capacity or quote udt,
+    // rounded up in favour of the seller
+    let min_received_amount = U256::mul(diff_sudt_amount, order_price * FEE_RATE_DECIMAL)
+      .div_ceil(PRICE_PARAM * (FEE_RATE_DECIMAL + fee_rate));
+    if U256::from(received_amount) < min_received_amount {
       return Err(Error::WrongSwapAmount);
     }
   } else {
@@ -193,6 +733,143 @@ fn validate_order_cells(index: usize) -> Result<(), Error> {
 }
 
 
+fn load_settlement(input_index: usize) -> Result<Option<Settlement>, Error> {
+  match load_action(input_index, Source::Input)? {
+    Action::Fill(settlement) => Ok(settlement),
+    _ => Err(Error::WrongAction),
+  }
+}
+
+// The outputs which settle an order input, given by the settlement of the fill action
+// or, without the settlement, the output at the same index as the input
+struct MatchedOutputs {
+  output_index: usize,
+  // receives the quote udt of a sudt/sudt order
+  quote_output_index: Option<usize>,
+  // SIDE_MAKER or SIDE_TAKER
+  side: Option<u8>,
+  // settled in a batch auction
+  clearing_price: Option<u64>,
+}
+
+fn load_matched_outputs(input_index: usize, inputs_count: usize, outputs_count: usize) -> Result<MatchedOutputs, Error> {
+  let matched = match load_settlement(input_index)? {
+    Some(settlement) => MatchedOutputs {
+      output_index: settlement.output_index,
+      quote_output_index: settlement.quote_output_index,
+      side: settlement.side,
+      clearing_price: settlement.clearing_price,
+    },
+    None => {
+      if inputs_count != outputs_count {
+        return Err(Error::InputsAndOutputsAmountNotSame);
+      }
+      MatchedOutputs {
+        output_index: input_index,
+        quote_output_index: None,
+        side: None,
+        clearing_price: None,
+      }
+    }
+  };
+  if matched.output_index >= outputs_count {
+    return Err(Error::IndexOutOfBound);
+  }
+  match matched.quote_output_index {
+    Some(index) if index >= outputs_count || index == matched.output_index => Err(Error::IndexOutOfBound),
+    _ => Ok(matched),
+  }
+}
+
+fn is_matched_to(matched: &MatchedOutputs, output_index: usize) -> bool {
+  matched.output_index == output_index || matched.quote_output_index == Some(output_index)
+}
+
+// The order inputs of the transaction which are filled, i.e. locked by any order lock
+// and neither cancelled nor modified
+fn is_filled_order(index: usize) -> Result<bool, Error> {
+  let script = load_script()?;
+  let lock = load_cell_lock(index, Source::Input)?;
+  if lock.code_hash().as_slice() != script.code_hash().as_slice() || lock.hash_type() != script.hash_type() {
+    return Ok(false);
+  }
+  match load_action(index, Source::Input)? {
+    Action::Fill(_) => Ok(true),
+    _ => Ok(false),
+  }
+}
+
+// Each output settles one order at most, otherwise two orders could be paid by the same cell
+fn check_outputs_matched_once(input_index: usize, matched: &MatchedOutputs, inputs_count: usize, outputs_count: usize) -> Result<(), Error> {
+  for index in 0..inputs_count {
+    if index == input_index || !is_filled_order(index)? {
+      continue;
+    }
+    let other = load_matched_outputs(index, inputs_count, outputs_count)?;
+    if is_matched_to(&other, matched.output_index)
+      || matched.quote_output_index.map_or(false, |quote_output_index| is_matched_to(&other, quote_output_index))
+    {
+      return Err(Error::DuplicateMatchedOutput);
+    }
+  }
+  Ok(())
+}
+
+// The index in the transaction inputs of the group input
+fn load_input_index(group_index: usize, inputs_count: usize) -> Result<Option<usize>, Error> {
+  let group_input = match load_input(group_index, Source::GroupInput) {
+    Ok(group_input) => group_input,
+    Err(SysError::IndexOutOfBound) => return Ok(None),
+    Err(err) => return Err(err.into()),
+  };
+  for index in 0..inputs_count {
+    let input = load_input(index, Source::Input)?;
+    if group_input.as_slice() == input.as_slice() {
+      return Ok(Some(index));
+    }
+  }
+  Err(Error::IndexOutOfBound)
+}
+
+// All orders of a batch auction are settled at the same clearing price, and the matcher can
+// only take the fees out of the order cells, so neither sudt nor capacity is created among them
+fn check_batch(clearing_price: u64, inputs_count: usize, outputs_count: usize) -> Result<(), Error> {
+  let mut inputs_sudt_amount = 0u128;
+  let mut outputs_sudt_amount = 0u128;
+  let mut inputs_capacity = 0u128;
+  let mut outputs_capacity = 0u128;
+  // The amounts of different udts can't be summed up, so the whole batch trades a single udt
+  let mut batch_type_hash = None;
+  for index in 0..inputs_count {
+    if !is_filled_order(index)? {
+      continue;
+    }
+    let matched = load_matched_outputs(index, inputs_count, outputs_count)?;
+    match matched.clearing_price {
+      Some(price) if price == clearing_price => {}
+      _ => return Err(Error::ClearingPriceNotSame),
+    }
+    let type_hash = load_cell_type_hash(index, Source::Input)?;
+    match batch_type_hash {
+      None => batch_type_hash = Some(type_hash),
+      Some(batch_type_hash) if batch_type_hash != type_hash => return Err(Error::BatchUDTNotSame),
+      _ => {}
+    }
+    inputs_sudt_amount = inputs_sudt_amount
+      .checked_add(load_udt_amount(index, Source::Input)?)
+      .ok_or(Error::BatchNotConserved)?;
+    outputs_sudt_amount = outputs_sudt_amount
+      .checked_add(load_udt_amount(matched.output_index, Source::Output)?)
+      .ok_or(Error::BatchNotConserved)?;
+    inputs_capacity += load_cell_capacity(index, Source::Input)? as u128;
+    outputs_capacity += load_cell_capacity(matched.output_index, Source::Output)? as u128;
+  }
+  if outputs_sudt_amount > inputs_sudt_amount || outputs_capacity > inputs_capacity {
+    return Err(Error::BatchNotConserved);
+  }
+  Ok(())
+}
+
 pub fn validate() -> Result<(), Error> {
   let tx = match load_transaction() {
     Ok(tx) => tx.raw(),
@@ -200,27 +877,72 @@ pub fn validate() -> Result<(), Error> {
   };
 
   let inputs_count = tx.inputs().len();
-  if inputs_count != tx.outputs().len() {
-    return Err(Error::InputsAndOutputsAmountNotSame);
-  }
+  let outputs_count = tx.outputs().len();
 
+  let mut batch_clearing_price = None;
   for group_index in 0..inputs_count {
-    match load_input(group_index, Source::GroupInput) {
-      Ok(group_input) => {
-        for index in 0..inputs_count {
-          let input = load_input(index, Source::Input).unwrap();
-          if group_input.as_slice() == input.as_slice() {
-            match validate_order_cells(index) {
-              Ok(_) => break,
-              Err(err) => return Err(err)
-            };
-          }
-        }
-      },
-      Err(_) => break,
+    let index = match load_input_index(group_index, inputs_count)? {
+      Some(index) => index,
+      None => break,
     };
+    let matched = load_matched_outputs(index, inputs_count, outputs_count)?;
+    check_outputs_matched_once(index, &matched, inputs_count, outputs_count)?;
+    check_matcher(index)?;
+    validate_order_cells(index, &matched, inputs_count, outputs_count)?;
+    if matched.clearing_price.is_some() {
+      batch_clearing_price = matched.clearing_price;
+    }
+  }
+  if let Some(clearing_price) = batch_clearing_price {
+    check_batch(clearing_price, inputs_count, outputs_count)?;
+  }
+
+  Ok(())
+
+}
+
+// The modified order keeps the owner, the udt, the sudt amount and the capacity, only the terms change
+fn validate_modified_order(input_index: usize, output_index: usize) -> Result<(), Error> {
+  if load_cell_lock_hash(input_index, Source::Input)? != load_cell_lock_hash(output_index, Source::Output)? {
+    return Err(Error::ModifiedOrderNotSame);
+  }
+  if load_cell_type_hash(input_index, Source::Input)? != load_cell_type_hash(output_index, Source::Output)? {
+    return Err(Error::ModifiedOrderNotSame);
+  }
+  if load_cell_capacity(input_index, Source::Input)? > load_cell_capacity(output_index, Source::Output)? {
+    return Err(Error::ModifiedOrderNotSame);
+  }
+  let input_order = parse_cell_data(input_index, Source::Input)?;
+  let output_order = parse_output_data(output_index, &input_order)?;
+  if !output_order.is_order
+    || input_order.sudt_amount != output_order.sudt_amount
+    || input_order.udt_extension != output_order.udt_extension
+  {
+    return Err(Error::ModifiedOrderNotSame);
   }
-  
   Ok(())
+}
 
+// Every input of the group is modified into its own output, the owner signature is checked by the caller
+pub fn validate_modify() -> Result<(), Error> {
+  let tx = load_transaction()?.raw();
+  let inputs_count = tx.inputs().len();
+  let outputs_count = tx.outputs().len();
+
+  for group_index in 0..inputs_count {
+    let index = match load_input_index(group_index, inputs_count)? {
+      Some(index) => index,
+      None => break,
+    };
+    let output_index = match load_action(index, Source::Input)? {
+      Action::Modify(output_index) => output_index,
+      _ => return Err(Error::WrongAction),
+    };
+    if output_index >= outputs_count {
+      return Err(Error::IndexOutOfBound);
+    }
+    validate_modified_order(index, output_index)?;
+  }
+
+  Ok(())
 }
@@ -0,0 +1,102 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+use share::error::Error;
+
+// The matcher fills an order input with the settlement in the Fill action of the input, see
+// `action.rs`. It is encoded as a molecule table:
+//
+// option ByteOpt (byte);
+// option Uint32Opt (Uint32);
+// option Uint64Opt (Uint64);
+//
+// table Settlement {
+//     output_index: Uint32,
+//     side: ByteOpt,                  // SIDE_MAKER or SIDE_TAKER
+//     quote_output_index: Uint32Opt,  // receives the quote udt of a sudt/sudt order
+//     clearing_price: Uint64Opt,      // the uniform price of a batch auction
+// }
+//
+// Fields appended by later schemas are ignored by this script, and missing trailing
+// fields are regarded as none.
+pub struct Settlement {
+  pub output_index: usize,
+  pub side: Option<u8>,
+  pub quote_output_index: Option<usize>,
+  pub clearing_price: Option<u64>,
+}
+
+pub const SIDE_MAKER: u8 = 0;
+pub const SIDE_TAKER: u8 = 1;
+
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+  if data.len() < offset + 4 {
+    return Err(Error::Encoding);
+  }
+  let mut buf = [0u8; 4];
+  buf.copy_from_slice(&data[offset..offset + 4]);
+  Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+  if data.len() < offset + 8 {
+    return Err(Error::Encoding);
+  }
+  let mut buf = [0u8; 8];
+  buf.copy_from_slice(&data[offset..offset + 8]);
+  Ok(u64::from_le_bytes(buf))
+}
+
+// The index-th field of a molecule table, None if the table is written by an older schema without it
+pub fn table_field(table: &[u8], index: usize) -> Result<Option<&[u8]>, Error> {
+  let total_size = read_u32(table, 0)? as usize;
+  if total_size != table.len() {
+    return Err(Error::Encoding);
+  }
+  if total_size == 4 {
+    return Ok(None);
+  }
+  let first_offset = read_u32(table, 4)? as usize;
+  if first_offset % 4 != 0 || first_offset < 8 || first_offset > total_size {
+    return Err(Error::Encoding);
+  }
+  let field_count = first_offset / 4 - 1;
+  if index >= field_count {
+    return Ok(None);
+  }
+  let start = read_u32(table, 4 + index * 4)? as usize;
+  let end = if index + 1 == field_count {
+    total_size
+  } else {
+    read_u32(table, 8 + index * 4)? as usize
+  };
+  if start > end || end > total_size {
+    return Err(Error::Encoding);
+  }
+  Ok(Some(&table[start..end]))
+}
+
+impl Settlement {
+  pub fn from_slice(data: &[u8]) -> Result<Self, Error> {
+    let output_index = match table_field(data, 0)? {
+      Some(field) if field.len() == 4 => read_u32(field, 0)? as usize,
+      _ => return Err(Error::Encoding),
+    };
+    let side = match table_field(data, 1)? {
+      Some(&[side]) if side == SIDE_MAKER || side == SIDE_TAKER => Some(side),
+      Some(&[]) | None => None,
+      Some(_) => return Err(Error::Encoding),
+    };
+    let quote_output_index = match table_field(data, 2)? {
+      Some(field) if field.len() == 4 => Some(read_u32(field, 0)? as usize),
+      Some(&[]) | None => None,
+      Some(_) => return Err(Error::Encoding),
+    };
+    let clearing_price = match table_field(data, 3)? {
+      Some(field) if field.len() == 8 => Some(read_u64(field, 0)?),
+      Some(&[]) | None => None,
+      Some(_) => return Err(Error::Encoding),
+    };
+    Ok(Settlement { output_index, side, quote_output_index, clearing_price })
+  }
+}
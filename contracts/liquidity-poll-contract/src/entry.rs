@@ -1,32 +1,34 @@
 // Import from `core` instead of from `std` since we are in no-std mode
 use core::result::Result;
 
-// Import heap related library from `alloc`
-// https://doc.rust-lang.org/alloc/index.html
-use alloc::{vec, vec::Vec};
-
 // Import CKB syscalls and structures
 // https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
 use ckb_std::{
-    debug,
-    high_level::{load_script, load_tx_hash},
+    ckb_constants::Source,
     ckb_types::{bytes::Bytes, prelude::*},
+    high_level::{load_cell_lock_hash, load_script, load_script_hash},
 };
 
 use share::error::Error;
 
-pub fn main() -> Result<(), Error> {
-    // remove below examples and write your code here
-
-    let script = load_script()?;
-    let args: Bytes = script.args().unpack();
-    debug!("script args is {:?}", args);
-
-    let tx_hash = load_tx_hash()?;
-    debug!("tx hash is {:?}", tx_hash);
+mod pool;
+mod registry;
+mod reserve;
+mod stable;
 
-    let _buf: Vec<_> = vec![0u8; 32];
-
-    Ok(())
+pub fn main() -> Result<(), Error> {
+    // The script is the type script of pool cells or of the pair registry, or the lock script of
+    // the sudt reserve cells of pools
+    let script_hash = load_script_hash()?;
+    match load_cell_lock_hash(0, Source::GroupInput) {
+        Ok(lock_hash) if lock_hash == script_hash => reserve::validate(),
+        _ => {
+            let args: Bytes = load_script()?.args().unpack();
+            if args.len() == registry::REGISTRY_ARGS_LEN {
+                registry::validate()
+            } else {
+                pool::validate()
+            }
+        }
+    }
 }
-
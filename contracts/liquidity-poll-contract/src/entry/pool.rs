@@ -0,0 +1,599 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    error::SysError,
+    high_level::{
+        load_cell_capacity, load_cell_data, load_cell_lock, load_cell_lock_hash,
+        load_cell_occupied_capacity, load_cell_type, load_cell_type_hash, load_header, load_input,
+        load_input_since, load_script, load_script_hash, QueryIter,
+    },
+};
+
+use share::constants::PRICE_PARAM;
+use share::error::Error;
+use share::hash::blake2b_256;
+use share::pool::{parse_pool_args, PoolArgs};
+use share::u256::U256;
+
+use super::registry::{
+    pair_hash, sorted_pair, REGISTRY_ARGS_LEN, REGISTRY_ENTRY_LEN, REGISTRY_HEADER_LEN,
+};
+use super::stable::compute_d;
+
+// type args: see `share::pool`, the pool id is checked by `check_pool_id` and the registry
+// by `check_registered`
+// data: ckb_reserve(u128) + sudt_reserve(u128) + lp_supply(u128)
+// + ckb_protocol_fee(u128) + sudt_protocol_fee(u128) + protocol_fee_share(u16)
+// + treasury_lock_hash([u8; 32]) + amplification(u64)
+// + sudt_price_cumulative(u128) + ckb_price_cumulative(u128) + last_timestamp(u64)
+// + ckb_multiplier(u64) + sudt_multiplier(u64), which bring both reserves to the same precision
+// on the StableSwap curve, e.g. 1 for CKB and 100 for a sudt of 6 decimals
+// A sUDT/sUDT pool keeps the reserve of its base sudt in the place of CKB, see `load_pool`
+const POOL_DATA_LEN: usize = 178;
+const SUDT_LEN: usize = 16;
+// The part of the initial LP supply which is never minted, so the supply can't be burned to zero
+// and the share price can't be inflated by a tiny first deposit
+const MINIMUM_LIQUIDITY: u128 = 1_000;
+// swap fee = FEE_RATE / FEE_RATE_DECIMAL = 0.3%, paid by the incoming asset
+const FEE_RATE: u128 = 30;
+const FEE_RATE_DECIMAL: u128 = 10_000;
+// protocol fee = protocol_fee_share / PROTOCOL_FEE_SHARE_DECIMAL of the swap fee, 0 by default
+const PROTOCOL_FEE_SHARE_DECIMAL: u128 = 10_000;
+// The amplification coefficient A of a StableSwap pool, 0 is a constant-product pool
+const MAX_AMPLIFICATION: u64 = 1_000_000;
+// An absolute or relative since by median time in seconds
+const SINCE_FLAGS_MASK: u64 = 0xff00_0000_0000_0000;
+const SINCE_VALUE_MASK: u64 = 0x00ff_ffff_ffff_ffff;
+const SINCE_ABSOLUTE_TIMESTAMP: u64 = 0x4000_0000_0000_0000;
+const SINCE_RELATIVE_TIMESTAMP: u64 = 0xc000_0000_0000_0000;
+
+struct PoolData {
+    ckb_reserve: u128,
+    sudt_reserve: u128,
+    lp_supply: u128,
+    ckb_protocol_fee: u128,
+    sudt_protocol_fee: u128,
+    protocol_fee_share: u16,
+    treasury_lock_hash: [u8; 32],
+    amplification: u64,
+    sudt_price_cumulative: u128,
+    ckb_price_cumulative: u128,
+    last_timestamp: u64,
+    ckb_multiplier: u64,
+    sudt_multiplier: u64,
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    let mut buf = [0u8; 16];
+    buf.copy_from_slice(&data[offset..offset + 16]);
+    u128::from_le_bytes(buf)
+}
+
+fn parse_pool_data(data: &[u8]) -> Result<PoolData, Error> {
+    if data.len() != POOL_DATA_LEN {
+        return Err(Error::WrongPoolData);
+    }
+    let mut pool = PoolData {
+        ckb_reserve: read_u128(data, 0),
+        sudt_reserve: read_u128(data, 16),
+        lp_supply: read_u128(data, 32),
+        ckb_protocol_fee: read_u128(data, 48),
+        sudt_protocol_fee: read_u128(data, 64),
+        protocol_fee_share: u16::from_le_bytes([data[80], data[81]]),
+        treasury_lock_hash: [0u8; 32],
+        amplification: read_u64(data, 114),
+        sudt_price_cumulative: read_u128(data, 122),
+        ckb_price_cumulative: read_u128(data, 138),
+        last_timestamp: read_u64(data, 154),
+        ckb_multiplier: read_u64(data, 162),
+        sudt_multiplier: read_u64(data, 170),
+    };
+    pool.treasury_lock_hash.copy_from_slice(&data[82..114]);
+    if pool.protocol_fee_share as u128 > PROTOCOL_FEE_SHARE_DECIMAL
+        || pool.amplification > MAX_AMPLIFICATION
+        || pool.ckb_multiplier == 0
+        || pool.sudt_multiplier == 0
+    {
+        return Err(Error::WrongPoolData);
+    }
+    Ok(pool)
+}
+
+fn load_pool_args() -> Result<PoolArgs, Error> {
+    let args: Bytes = load_script()?.args().unpack();
+    parse_pool_args(&args)
+}
+
+// The index of the pool cell in the inputs or the outputs, a transaction touches a pool once at most
+fn find_pool_cell(source: Source) -> Result<Option<usize>, Error> {
+    let script_hash = load_script_hash()?;
+    let mut pool_index = None;
+    for (index, type_hash) in QueryIter::new(load_cell_type_hash, source).enumerate() {
+        if type_hash == Some(script_hash) {
+            if pool_index.is_some() {
+                return Err(Error::PoolCellNotUnique);
+            }
+            pool_index = Some(index);
+        }
+    }
+    Ok(pool_index)
+}
+
+// The sudt reserve is kept in the cell right after the pool cell, and the base sudt reserve of
+// a sUDT/sUDT pool in the next one. Both are locked by this script with the pool type hash as
+// args, see `reserve.rs`
+fn load_sudt_reserve(
+    reserve_index: usize,
+    sudt_type_hash: &[u8; 32],
+    source: Source,
+) -> Result<u128, Error> {
+    match load_cell_type_hash(reserve_index, source) {
+        Ok(Some(type_hash)) if &type_hash == sudt_type_hash => {}
+        Ok(_) | Err(SysError::IndexOutOfBound) => return Err(Error::WrongReserveCell),
+        Err(err) => return Err(err.into()),
+    }
+
+    let script = load_script()?;
+    let lock = load_cell_lock(reserve_index, source)?;
+    let lock_args: Bytes = lock.args().unpack();
+    if lock.code_hash().as_slice() != script.code_hash().as_slice()
+        || lock.hash_type() != script.hash_type()
+        || lock_args[..] != load_script_hash()?[..]
+    {
+        return Err(Error::WrongReserveCell);
+    }
+
+    let data = load_cell_data(reserve_index, source)?;
+    if data.len() < SUDT_LEN {
+        return Err(Error::WrongReserveCell);
+    }
+    Ok(read_u128(&data, 0))
+}
+
+// The reserves in the pool data plus the protocol fees are the free capacity of the pool cell
+// and the sudt amount of its reserve cell. The base sudt of a sUDT/sUDT pool takes the place of
+// CKB, with the sudt amount of its own reserve cell.
+fn load_pool(pool_index: usize, source: Source) -> Result<PoolData, Error> {
+    let pool = parse_pool_data(&load_cell_data(pool_index, source)?)?;
+    let pool_args = load_pool_args()?;
+    let ckb_amount = if pool_args.is_sudt_pair() {
+        load_sudt_reserve(pool_index + 2, &pool_args.base_type_hash, source)?
+    } else {
+        let free_capacity = load_cell_capacity(pool_index, source)?
+            - load_cell_occupied_capacity(pool_index, source)?;
+        free_capacity as u128
+    };
+    let sudt_amount = load_sudt_reserve(pool_index + 1, &pool_args.sudt_type_hash, source)?;
+    if pool.ckb_reserve.checked_add(pool.ckb_protocol_fee) != Some(ckb_amount)
+        || pool.sudt_reserve.checked_add(pool.sudt_protocol_fee) != Some(sudt_amount)
+    {
+        return Err(Error::ReserveNotMatch);
+    }
+    Ok(pool)
+}
+
+// reserve * FEE_RATE_DECIMAL - amount_in * FEE_RATE
+fn adjusted_reserve(reserve: u128, amount_in: u128) -> Result<u128, Error> {
+    reserve
+        .checked_mul(FEE_RATE_DECIMAL)
+        .map(|reserve| reserve - amount_in * FEE_RATE)
+        .ok_or(Error::PoolInvariantBroken)
+}
+
+fn scaled_reserve(reserve: u128, multiplier: u64) -> Result<u128, Error> {
+    reserve
+        .checked_mul(multiplier as u128)
+        .ok_or(Error::PoolInvariantBroken)
+}
+
+// The protocol takes its share of the swap fee out of the incoming asset, which is kept in the pool
+// cells apart from the reserves until the treasury withdraws it. Returns the output reserve with
+// the new protocol fee, which is what the swap pays into the pool.
+fn check_protocol_fee(
+    input_reserve: u128,
+    input_fee: u128,
+    output_reserve: u128,
+    output_fee: u128,
+    protocol_fee_share: u16,
+) -> Result<u128, Error> {
+    if output_fee < input_fee {
+        return Err(Error::WrongProtocolFee);
+    }
+    let fee = output_fee - input_fee;
+    let amount_in = output_reserve
+        .checked_add(fee)
+        .ok_or(Error::WrongProtocolFee)?
+        .saturating_sub(input_reserve);
+    let protocol_fee = U256::mul(amount_in, FEE_RATE * protocol_fee_share as u128)
+        .div_floor(FEE_RATE_DECIMAL * PROTOCOL_FEE_SHARE_DECIMAL);
+    if U256::from(fee) != protocol_fee {
+        return Err(Error::WrongProtocolFee);
+    }
+    Ok(output_reserve + fee)
+}
+
+// x * y = k, or D of a StableSwap pool, never decreases even after the fee of the incoming asset
+// is taken out: (x' - fee * x_in) * (y' - fee * y_in) >= x * y
+fn check_invariant(input_pool: &PoolData, output_pool: &PoolData) -> Result<(), Error> {
+    let share = input_pool.protocol_fee_share;
+    let ckb_reserve = check_protocol_fee(
+        input_pool.ckb_reserve,
+        input_pool.ckb_protocol_fee,
+        output_pool.ckb_reserve,
+        output_pool.ckb_protocol_fee,
+        share,
+    )?;
+    let sudt_reserve = check_protocol_fee(
+        input_pool.sudt_reserve,
+        input_pool.sudt_protocol_fee,
+        output_pool.sudt_reserve,
+        output_pool.sudt_protocol_fee,
+        share,
+    )?;
+    if output_pool.ckb_reserve == 0 || output_pool.sudt_reserve == 0 {
+        return Err(Error::PoolInvariantBroken);
+    }
+    let ckb_in = ckb_reserve.saturating_sub(input_pool.ckb_reserve);
+    let sudt_in = sudt_reserve.saturating_sub(input_pool.sudt_reserve);
+
+    let input_x = adjusted_reserve(input_pool.ckb_reserve, 0)?;
+    let input_y = adjusted_reserve(input_pool.sudt_reserve, 0)?;
+    let output_x = adjusted_reserve(ckb_reserve, ckb_in)?;
+    let output_y = adjusted_reserve(sudt_reserve, sudt_in)?;
+    let preserved = match input_pool.amplification {
+        0 => U256::mul(output_x, output_y) >= U256::mul(input_x, input_y),
+        // D is homogeneous, so the reserves scaled by FEE_RATE_DECIMAL are compared the same way
+        amplification => {
+            let (ckb_multiplier, sudt_multiplier) =
+                (input_pool.ckb_multiplier, input_pool.sudt_multiplier);
+            let output_d = compute_d(
+                scaled_reserve(output_x, ckb_multiplier)?,
+                scaled_reserve(output_y, sudt_multiplier)?,
+                amplification,
+            )?;
+            let input_d = compute_d(
+                scaled_reserve(input_x, ckb_multiplier)?,
+                scaled_reserve(input_y, sudt_multiplier)?,
+                amplification,
+            )?;
+            output_d >= input_d
+        }
+    };
+    if !preserved {
+        return Err(Error::PoolInvariantBroken);
+    }
+    Ok(())
+}
+
+// The transaction is committed after its header deps, and after the absolute timestamp since of
+// the pool input, so the latest of them is the current time in milliseconds
+fn load_current_timestamp(input_index: Option<usize>) -> Result<u64, Error> {
+    let mut timestamp = QueryIter::new(load_header, Source::HeaderDep)
+        .map(|header| {
+            let timestamp: u64 = header.raw().timestamp().unpack();
+            timestamp
+        })
+        .max();
+    if let Some(input_index) = input_index {
+        let since = load_input_since(input_index, Source::Input)?;
+        if since & SINCE_FLAGS_MASK == SINCE_ABSOLUTE_TIMESTAMP {
+            let since_timestamp = (since & SINCE_VALUE_MASK).saturating_mul(1000);
+            timestamp = timestamp.max(Some(since_timestamp));
+        }
+    }
+    timestamp.ok_or(Error::TimestampNotFound)
+}
+
+// The pool input is committed at least its relative timestamp since before the transaction, in
+// milliseconds. Nothing else proves how long its price has stood: a header dep can be as old as
+// the pool, and a transaction right after the pool input in the same block can use a fresh one.
+fn load_pool_age(input_index: usize) -> Result<u64, Error> {
+    let since = load_input_since(input_index, Source::Input)?;
+    if since & SINCE_FLAGS_MASK == SINCE_RELATIVE_TIMESTAMP {
+        Ok((since & SINCE_VALUE_MASK).saturating_mul(1000))
+    } else {
+        Ok(0)
+    }
+}
+
+// The price of the denominator asset in the numerator asset, times PRICE_PARAM
+fn reserve_price(numerator_reserve: u128, denominator_reserve: u128) -> u128 {
+    if denominator_reserve == 0 {
+        return 0;
+    }
+    U256::mul(numerator_reserve, PRICE_PARAM)
+        .div_floor(denominator_reserve)
+        .to_u128()
+        .unwrap_or(u128::MAX)
+}
+
+// The accumulators add up the prices before the transaction over the milliseconds since the last
+// update, wrapping on overflow, so other scripts get the TWAP between two snapshots of the pool as
+// cumulative_2.wrapping_sub(cumulative_1) / (timestamp_2 - timestamp_1). Only the age of the pool
+// input counts, so a price set in the same block is never credited.
+fn check_price_accumulators(
+    input_index: usize,
+    input_pool: &PoolData,
+    output_pool: &PoolData,
+) -> Result<(), Error> {
+    let timestamp = load_current_timestamp(Some(input_index))?;
+    if timestamp < input_pool.last_timestamp || output_pool.last_timestamp != timestamp {
+        return Err(Error::WrongPriceAccumulator);
+    }
+    let elapsed = (timestamp - input_pool.last_timestamp).min(load_pool_age(input_index)?) as u128;
+    let sudt_price = reserve_price(input_pool.ckb_reserve, input_pool.sudt_reserve);
+    let ckb_price = reserve_price(input_pool.sudt_reserve, input_pool.ckb_reserve);
+    if output_pool.sudt_price_cumulative
+        != input_pool.sudt_price_cumulative.wrapping_add(sudt_price.wrapping_mul(elapsed))
+        || output_pool.ckb_price_cumulative
+            != input_pool.ckb_price_cumulative.wrapping_add(ckb_price.wrapping_mul(elapsed))
+    {
+        return Err(Error::WrongPriceAccumulator);
+    }
+    Ok(())
+}
+
+// The LP token is a sudt whose owner lock is the reserve lock of the pool, so it runs in owner mode
+// whenever the pool is touched, and the pool type script checks every mint and burn of it. Its
+// script is pinned by the registry the pool is created in.
+fn sum_lp_amount(reserve_lock_hash: &[u8; 32], source: Source) -> Result<u128, Error> {
+    let pool_args = load_pool_args()?;
+    let mut lp_amount = 0u128;
+    for (index, type_script) in QueryIter::new(load_cell_type, source).enumerate() {
+        let is_lp = match type_script {
+            Some(script) => {
+                let args: Bytes = script.args().unpack();
+                script.code_hash().as_slice() == &pool_args.lp_code_hash[..]
+                    && script.hash_type().as_slice() == &[pool_args.lp_hash_type]
+                    && args[..] == reserve_lock_hash[..]
+            }
+            None => false,
+        };
+        if is_lp {
+            let data = load_cell_data(index, source)?;
+            if data.len() < SUDT_LEN {
+                return Err(Error::WrongDataLengthOrFormat);
+            }
+            lp_amount = lp_amount
+                .checked_add(read_u128(&data, 0))
+                .ok_or(Error::LPSupplyNotMatch)?;
+        }
+    }
+    Ok(lp_amount)
+}
+
+// The LP tokens minted or burned by the transaction are exactly the change of the LP supply
+fn check_lp_supply(
+    output_index: usize,
+    input_supply: u128,
+    output_supply: u128,
+) -> Result<(), Error> {
+    let reserve_lock_hash = load_cell_lock_hash(output_index + 1, Source::Output)?;
+    let inputs_lp_amount = sum_lp_amount(&reserve_lock_hash, Source::Input)?;
+    let outputs_lp_amount = sum_lp_amount(&reserve_lock_hash, Source::Output)?;
+    if outputs_lp_amount.checked_add(input_supply) != inputs_lp_amount.checked_add(output_supply) {
+        return Err(Error::LPSupplyNotMatch);
+    }
+    Ok(())
+}
+
+// The pool id is blake2b(first input outpoint || the sorted type hashes of the pair), so a pool
+// can't be created twice, and it names the pair it trades
+fn check_pool_id(pool_args: &PoolArgs) -> Result<(), Error> {
+    let first_input = load_input(0, Source::Input)?;
+    let (lower_hash, higher_hash) = sorted_pair(pool_args);
+    let pool_id = blake2b_256(&[
+        first_input.previous_output().as_slice(),
+        &lower_hash,
+        &higher_hash,
+    ]);
+    if pool_id != pool_args.pool_id {
+        return Err(Error::WrongPoolId);
+    }
+    Ok(())
+}
+
+// The pool is created together with its entry in the registry, whose type script makes sure
+// the pair has no other pool there. The registry is of this script, otherwise it could hold
+// made-up entries.
+fn check_registered(pool_args: &PoolArgs, treasury_lock_hash: &[u8; 32]) -> Result<(), Error> {
+    let script_hash = load_script_hash()?;
+    let pair_hash = pair_hash(pool_args);
+    let registry_index = QueryIter::new(load_cell_type_hash, Source::Output)
+        .position(|type_hash| type_hash == Some(pool_args.registry_type_hash))
+        .ok_or(Error::PoolNotRegistered)?;
+    let registry_type = match load_cell_type(registry_index, Source::Output)? {
+        Some(script) => script,
+        None => return Err(Error::PoolNotRegistered),
+    };
+    let script = load_script()?;
+    let registry_args: Bytes = registry_type.args().unpack();
+    if registry_type.code_hash().as_slice() != script.code_hash().as_slice()
+        || registry_type.hash_type() != script.hash_type()
+        || registry_args.len() != REGISTRY_ARGS_LEN
+    {
+        return Err(Error::PoolNotRegistered);
+    }
+    let data = load_cell_data(registry_index, Source::Output)?;
+    let entries = data.get(REGISTRY_HEADER_LEN..).ok_or(Error::PoolNotRegistered)?;
+    // The treasury of the pool is the one of the registry, not one picked by the creator
+    if data[33..REGISTRY_HEADER_LEN] != treasury_lock_hash[..] {
+        return Err(Error::TreasuryNotFound);
+    }
+    let registered = entries
+        .chunks_exact(REGISTRY_ENTRY_LEN)
+        .any(|entry| entry[..32] == pair_hash[..] && entry[32..] == script_hash[..]);
+    if !registered {
+        return Err(Error::PoolNotRegistered);
+    }
+    Ok(())
+}
+
+// The first deposit gets sqrt(x * y) LP tokens except MINIMUM_LIQUIDITY, which is locked forever
+fn validate_creation(output_index: usize) -> Result<(), Error> {
+    let pool = load_pool(output_index, Source::Output)?;
+    if pool.ckb_reserve == 0 || pool.sudt_reserve == 0 {
+        return Err(Error::PoolInvariantBroken);
+    }
+    // The protocol fee is off until the treasury switches it on
+    if pool.ckb_protocol_fee != 0 || pool.sudt_protocol_fee != 0 || pool.protocol_fee_share != 0 {
+        return Err(Error::WrongProtocolFee);
+    }
+    if pool.sudt_price_cumulative != 0
+        || pool.ckb_price_cumulative != 0
+        || pool.last_timestamp != load_current_timestamp(None)?
+    {
+        return Err(Error::WrongPriceAccumulator);
+    }
+    if pool.lp_supply != U256::mul(pool.ckb_reserve, pool.sudt_reserve).sqrt()
+        || pool.lp_supply <= MINIMUM_LIQUIDITY
+    {
+        return Err(Error::WrongLPAmount);
+    }
+    check_lp_supply(output_index, MINIMUM_LIQUIDITY, pool.lp_supply)?;
+
+    let pool_args = load_pool_args()?;
+    check_pool_id(&pool_args)?;
+    check_registered(&pool_args, &pool.treasury_lock_hash)
+}
+
+// The minted LP tokens are no more than the share of either deposited asset
+fn check_deposit(input_pool: &PoolData, output_pool: &PoolData) -> Result<(), Error> {
+    if output_pool.ckb_reserve < input_pool.ckb_reserve
+        || output_pool.sudt_reserve < input_pool.sudt_reserve
+    {
+        return Err(Error::WrongLPAmount);
+    }
+    let minted = U256::from(output_pool.lp_supply - input_pool.lp_supply);
+    let ckb_in = output_pool.ckb_reserve - input_pool.ckb_reserve;
+    let sudt_in = output_pool.sudt_reserve - input_pool.sudt_reserve;
+    let ckb_share = U256::mul(ckb_in, input_pool.lp_supply).div_floor(input_pool.ckb_reserve);
+    let sudt_share = U256::mul(sudt_in, input_pool.lp_supply).div_floor(input_pool.sudt_reserve);
+    if minted > ckb_share || minted > sudt_share {
+        return Err(Error::WrongLPAmount);
+    }
+    Ok(())
+}
+
+// The withdrawn assets are no more than the share of the burned LP tokens
+fn check_withdrawal(input_pool: &PoolData, output_pool: &PoolData) -> Result<(), Error> {
+    let burned = input_pool.lp_supply - output_pool.lp_supply;
+    let max_ckb_out = U256::mul(burned, input_pool.ckb_reserve).div_floor(input_pool.lp_supply);
+    let max_sudt_out = U256::mul(burned, input_pool.sudt_reserve).div_floor(input_pool.lp_supply);
+    let ckb_out = input_pool.ckb_reserve.saturating_sub(output_pool.ckb_reserve);
+    let sudt_out = input_pool.sudt_reserve.saturating_sub(output_pool.sudt_reserve);
+    if U256::from(ckb_out) > max_ckb_out || U256::from(sudt_out) > max_sudt_out {
+        return Err(Error::WrongLPAmount);
+    }
+    if output_pool.lp_supply < MINIMUM_LIQUIDITY {
+        return Err(Error::WrongLPAmount);
+    }
+    Ok(())
+}
+
+fn is_treasury_spent(treasury_lock_hash: &[u8; 32]) -> bool {
+    QueryIter::new(load_cell_lock_hash, Source::Input)
+        .any(|lock_hash| &lock_hash == treasury_lock_hash)
+}
+
+// The treasury withdraws the protocol fees and switches the fee share, without touching
+// the reserves or the LP supply
+fn validate_treasury(
+    output_index: usize,
+    input_pool: &PoolData,
+    output_pool: &PoolData,
+) -> Result<(), Error> {
+    check_lp_supply(output_index, input_pool.lp_supply, output_pool.lp_supply)?;
+    if output_pool.ckb_reserve != input_pool.ckb_reserve
+        || output_pool.sudt_reserve != input_pool.sudt_reserve
+        || output_pool.lp_supply != input_pool.lp_supply
+        || output_pool.ckb_protocol_fee > input_pool.ckb_protocol_fee
+        || output_pool.sudt_protocol_fee > input_pool.sudt_protocol_fee
+    {
+        return Err(Error::WrongProtocolFee);
+    }
+    Ok(())
+}
+
+// The pool cell keeps its lock, and no capacity is taken out of the cells which don't hold the CKB
+// reserve: the reserve cells, and the pool cell of a sUDT/sUDT pool
+fn check_pool_cells(input_index: usize, output_index: usize) -> Result<(), Error> {
+    if load_cell_lock_hash(output_index, Source::Output)?
+        != load_cell_lock_hash(input_index, Source::Input)?
+    {
+        return Err(Error::WrongPoolLock);
+    }
+    let (first_offset, last_offset) = if load_pool_args()?.is_sudt_pair() {
+        (0, 2)
+    } else {
+        (1, 1)
+    };
+    for offset in first_offset..=last_offset {
+        if load_cell_capacity(output_index + offset, Source::Output)?
+            < load_cell_capacity(input_index + offset, Source::Input)?
+        {
+            return Err(Error::PoolCapacityDecreased);
+        }
+    }
+    Ok(())
+}
+
+// A swap keeps the LP supply, a deposit mints LP tokens and a withdrawal burns them
+fn validate_update(input_index: usize, output_index: usize) -> Result<(), Error> {
+    let input_pool = load_pool(input_index, Source::Input)?;
+    let output_pool = load_pool(output_index, Source::Output)?;
+    check_pool_cells(input_index, output_index)?;
+    // The curve of a pool is fixed at creation
+    if output_pool.amplification != input_pool.amplification
+        || output_pool.ckb_multiplier != input_pool.ckb_multiplier
+        || output_pool.sudt_multiplier != input_pool.sudt_multiplier
+    {
+        return Err(Error::WrongPoolData);
+    }
+    check_price_accumulators(input_index, &input_pool, &output_pool)?;
+    if is_treasury_spent(&input_pool.treasury_lock_hash) {
+        return validate_treasury(output_index, &input_pool, &output_pool);
+    }
+    if output_pool.protocol_fee_share != input_pool.protocol_fee_share
+        || output_pool.treasury_lock_hash != input_pool.treasury_lock_hash
+    {
+        return Err(Error::TreasuryNotFound);
+    }
+
+    check_lp_supply(output_index, input_pool.lp_supply, output_pool.lp_supply)?;
+    if output_pool.lp_supply != input_pool.lp_supply
+        && (output_pool.ckb_protocol_fee != input_pool.ckb_protocol_fee
+            || output_pool.sudt_protocol_fee != input_pool.sudt_protocol_fee)
+    {
+        return Err(Error::WrongProtocolFee);
+    }
+    if output_pool.lp_supply > input_pool.lp_supply {
+        check_deposit(&input_pool, &output_pool)
+    } else if output_pool.lp_supply < input_pool.lp_supply {
+        check_withdrawal(&input_pool, &output_pool)
+    } else {
+        check_invariant(&input_pool, &output_pool)
+    }
+}
+
+pub fn validate() -> Result<(), Error> {
+    let input_index = find_pool_cell(Source::Input)?;
+    let output_index = find_pool_cell(Source::Output)?;
+    match (input_index, output_index) {
+        (None, Some(output_index)) => validate_creation(output_index),
+        (Some(input_index), Some(output_index)) => validate_update(input_index, output_index),
+        // The reserves can never be taken away with the pool cell
+        _ => Err(Error::PoolCellMissing),
+    }
+}
@@ -0,0 +1,146 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    high_level::{
+        load_cell_data, load_cell_type, load_cell_type_hash, load_input, load_script,
+        load_script_hash, QueryIter,
+    },
+};
+
+use share::error::Error;
+use share::hash::blake2b_256;
+use share::pool::{parse_pool_args, PoolArgs};
+
+// type args: registry_id([u8; 32]), blake2b(first input outpoint || output index as u64) of
+// the transaction which creates the registry, like the type id
+pub const REGISTRY_ARGS_LEN: usize = 32;
+// data: lp_code_hash([u8; 32]) + lp_hash_type(u8) of the sudt script of the LP tokens
+// + treasury_lock_hash([u8; 32]) of the treasury which may switch on the protocol fee, both fixed
+// at creation and taken by every pool of the registry, followed by entries of pair_hash([u8; 32])
+// + pool_type_hash([u8; 32]), which map every pair to its canonical pool, so other scripts can
+// check a pool against the registry in their cell deps
+pub const REGISTRY_HEADER_LEN: usize = 65;
+pub const REGISTRY_ENTRY_LEN: usize = 64;
+
+// The type hashes of the pair of a pool in ascending order, where CKB takes CKB_TYPE_HASH
+pub fn sorted_pair(pool_args: &PoolArgs) -> ([u8; 32], [u8; 32]) {
+    let (base_type_hash, sudt_type_hash) = (pool_args.base_type_hash, pool_args.sudt_type_hash);
+    if base_type_hash <= sudt_type_hash {
+        (base_type_hash, sudt_type_hash)
+    } else {
+        (sudt_type_hash, base_type_hash)
+    }
+}
+
+pub fn pair_hash(pool_args: &PoolArgs) -> [u8; 32] {
+    let (lower_hash, higher_hash) = sorted_pair(pool_args);
+    blake2b_256(&[&lower_hash, &higher_hash])
+}
+
+fn find_registry_cell(source: Source) -> Result<Option<usize>, Error> {
+    let script_hash = load_script_hash()?;
+    let mut registry_index = None;
+    for (index, type_hash) in QueryIter::new(load_cell_type_hash, source).enumerate() {
+        if type_hash == Some(script_hash) {
+            if registry_index.is_some() {
+                return Err(Error::WrongRegistryData);
+            }
+            registry_index = Some(index);
+        }
+    }
+    Ok(registry_index)
+}
+
+// The registry starts without entries, and its id can't be taken by any other cell
+fn validate_creation(output_index: usize) -> Result<(), Error> {
+    let args: Bytes = load_script()?.args().unpack();
+    let first_input = load_input(0, Source::Input)?;
+    let registry_id = blake2b_256(&[
+        first_input.previous_output().as_slice(),
+        &(output_index as u64).to_le_bytes(),
+    ]);
+    if args[..] != registry_id[..] {
+        return Err(Error::WrongPoolId);
+    }
+    if load_cell_data(output_index, Source::Output)?.len() != REGISTRY_HEADER_LEN {
+        return Err(Error::WrongRegistryData);
+    }
+    Ok(())
+}
+
+// The pool of a new entry is created in the transaction by this script, for the pair of the entry
+// and in this registry. A cell of any other type script could take the pair with pool args, and a
+// pool of any other LP token script could mint LP tokens out of nothing.
+fn check_new_entry(header: &[u8], entry: &[u8]) -> Result<(), Error> {
+    let pool_type_hash = &entry[32..];
+    let is_pool = |type_hash: Option<[u8; 32]>| {
+        type_hash.map_or(false, |type_hash| type_hash[..] == pool_type_hash[..])
+    };
+    if QueryIter::new(load_cell_type_hash, Source::Input).any(is_pool) {
+        return Err(Error::WrongRegistryData);
+    }
+    let pool_index = QueryIter::new(load_cell_type_hash, Source::Output)
+        .position(is_pool)
+        .ok_or(Error::WrongRegistryData)?;
+    let pool_type = match load_cell_type(pool_index, Source::Output)? {
+        Some(script) => script,
+        None => return Err(Error::WrongRegistryData),
+    };
+    let script = load_script()?;
+    if pool_type.code_hash().as_slice() != script.code_hash().as_slice()
+        || pool_type.hash_type() != script.hash_type()
+    {
+        return Err(Error::WrongRegistryData);
+    }
+    let pool_args: Bytes = pool_type.args().unpack();
+    let pool_args = parse_pool_args(&pool_args)?;
+    if pair_hash(&pool_args)[..] != entry[..32]
+        || pool_args.registry_type_hash != load_script_hash()?
+        || pool_args.lp_code_hash[..] != header[..32]
+        || pool_args.lp_hash_type != header[32]
+    {
+        return Err(Error::WrongRegistryData);
+    }
+    Ok(())
+}
+
+// Entries are only appended behind the header, and a pair is registered once at most
+fn validate_update(input_index: usize, output_index: usize) -> Result<(), Error> {
+    let input_data = load_cell_data(input_index, Source::Input)?;
+    let output_data = load_cell_data(output_index, Source::Output)?;
+    if input_data.len() < REGISTRY_HEADER_LEN
+        || output_data.len() < input_data.len()
+        || (output_data.len() - REGISTRY_HEADER_LEN) % REGISTRY_ENTRY_LEN != 0
+        || output_data[..input_data.len()] != input_data[..]
+    {
+        return Err(Error::WrongRegistryData);
+    }
+
+    for start in (input_data.len()..output_data.len()).step_by(REGISTRY_ENTRY_LEN) {
+        let entry = &output_data[start..start + REGISTRY_ENTRY_LEN];
+        let registered = output_data[REGISTRY_HEADER_LEN..start]
+            .chunks_exact(REGISTRY_ENTRY_LEN)
+            .any(|other| other[..32] == entry[..32]);
+        if registered {
+            return Err(Error::PairAlreadyRegistered);
+        }
+        check_new_entry(&output_data[..REGISTRY_HEADER_LEN], entry)?;
+    }
+    Ok(())
+}
+
+pub fn validate() -> Result<(), Error> {
+    let input_index = find_registry_cell(Source::Input)?;
+    let output_index = find_registry_cell(Source::Output)?;
+    match (input_index, output_index) {
+        (None, Some(output_index)) => validate_creation(output_index),
+        (Some(input_index), Some(output_index)) => validate_update(input_index, output_index),
+        // The registry lives forever, otherwise a pair could be registered again
+        _ => Err(Error::WrongRegistryData),
+    }
+}
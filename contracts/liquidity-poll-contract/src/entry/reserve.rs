@@ -0,0 +1,43 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    high_level::{load_cell_type_hash, load_script, QueryIter},
+};
+
+use share::error::Error;
+
+fn is_pool(type_hash: Option<[u8; 32]>, pool_type_hash: &[u8]) -> bool {
+    type_hash.map_or(false, |type_hash| type_hash[..] == pool_type_hash[..])
+}
+
+// The sudt reserve cells of a pool are locked by this script with the type hash of the pool as
+// args, so they can only be spent together with the pool cell, whose type script checks the
+// reserves.
+// The lock hash is also the owner of the LP sudt of the pool, so the creator of a pool spends a plain
+// cell of this lock to mint the initial LP tokens.
+pub fn validate() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    if args.len() != 32 {
+        return Err(Error::Encoding);
+    }
+
+    let pool_spent = QueryIter::new(load_cell_type_hash, Source::Input)
+        .any(|type_hash| is_pool(type_hash, &args));
+    if pool_spent {
+        return Ok(());
+    }
+    let pool_created = QueryIter::new(load_cell_type_hash, Source::Output)
+        .any(|type_hash| is_pool(type_hash, &args));
+    let plain_cells = QueryIter::new(load_cell_type_hash, Source::GroupInput)
+        .all(|type_hash| type_hash.is_none());
+    if !pool_created || !plain_cells {
+        return Err(Error::PoolNotFound);
+    }
+    Ok(())
+}
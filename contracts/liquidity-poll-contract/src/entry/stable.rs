@@ -0,0 +1,61 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+use share::error::Error;
+use share::u256::U256;
+
+// Newton's method converges in a few rounds for any sane pool, the bound keeps the cycles of
+// a pathological pool finite
+const MAX_ITERATIONS: usize = 64;
+// The pool holds 2 assets, n = 2 and n ^ n = 4
+const N_COINS: u128 = 2;
+const N_POW_N: u128 = 4;
+
+fn checked_mul_div(a: u128, b: u128, divisor: u128) -> Result<u128, Error> {
+    if divisor == 0 {
+        return Err(Error::PoolInvariantBroken);
+    }
+    U256::mul(a, b)
+        .div_floor(divisor)
+        .to_u128()
+        .ok_or(Error::PoolInvariantBroken)
+}
+
+// The StableSwap invariant D of reserves x and y with the amplification coefficient A:
+// A * n^n * (x + y) + D = A * n^n * D + D^(n+1) / (n^n * x * y)
+// It is solved with Newton's method from D = x + y, where D_P = D^(n+1) / (n^n * x * y):
+// D' = (A * n^n * S + n * D_P) * D / ((A * n^n - 1) * D + (n + 1) * D_P)
+pub fn compute_d(x: u128, y: u128, amplification: u64) -> Result<u128, Error> {
+    let sum = x.checked_add(y).ok_or(Error::PoolInvariantBroken)?;
+    if x == 0 || y == 0 || amplification == 0 {
+        return Err(Error::PoolInvariantBroken);
+    }
+    let ann = amplification as u128 * N_POW_N;
+    let ann_sum = ann.checked_mul(sum).ok_or(Error::PoolInvariantBroken)?;
+
+    let n_x = x.checked_mul(N_COINS).ok_or(Error::PoolInvariantBroken)?;
+    let n_y = y.checked_mul(N_COINS).ok_or(Error::PoolInvariantBroken)?;
+
+    let mut d = sum;
+    for _ in 0..MAX_ITERATIONS {
+        let mut d_p = checked_mul_div(d, d, n_x)?;
+        d_p = checked_mul_div(d_p, d, n_y)?;
+        let numerator = d_p
+            .checked_mul(N_COINS)
+            .and_then(|d_p| d_p.checked_add(ann_sum))
+            .ok_or(Error::PoolInvariantBroken)?;
+        let denominator = (ann - 1)
+            .checked_mul(d)
+            .and_then(|ann_d| {
+                d_p.checked_mul(N_COINS + 1)
+                    .and_then(|d_p| ann_d.checked_add(d_p))
+            })
+            .ok_or(Error::PoolInvariantBroken)?;
+        let previous = d;
+        d = checked_mul_div(numerator, d, denominator)?;
+        if d.max(previous) - d.min(previous) <= 1 {
+            return Ok(d);
+        }
+    }
+    Err(Error::PoolInvariantBroken)
+}
@@ -0,0 +1,189 @@
+// Import from `core` instead of from `std` since we are in no-std mode
+use core::result::Result;
+
+// Import CKB syscalls and structures
+// https://nervosnetwork.github.io/ckb-std/riscv64imac-unknown-none-elf/doc/ckb_std/index.html
+use ckb_std::{
+    ckb_constants::Source,
+    ckb_types::{bytes::Bytes, prelude::*},
+    default_alloc,
+    error::SysError,
+    high_level::{
+        load_cell_capacity, load_cell_data, load_cell_lock_hash, load_cell_type,
+        load_cell_type_hash, load_script, load_script_hash, QueryIter,
+    },
+};
+
+use share::error::Error;
+use share::pool::parse_pool_args;
+use share::signature;
+
+// Alloc 4K fast HEAP + 2M HEAP to receives PrefilledData
+default_alloc!(4 * 1024, 2048 * 1024, 64);
+
+// lock args: pubkey_hash([u8; 20]) of the owner who can cancel the request
+// + pool_type_hash([u8; 32]) of the pool the request is executed against
+const ARGS_LEN: usize = 52;
+// sudt_amount(u128) + request_type(u8) + user_lock_hash([u8; 32])
+// + min_amount_out(u128) + min_capacity_out(u64)
+const REQUEST_DATA_LEN: usize = 73;
+const SUDT_LEN: usize = 16;
+
+// CKB is swapped for the sudt of the pool, the base sudt of a sUDT/sUDT pool takes the place of
+// CKB in every request
+const SWAP_TO_SUDT: u8 = 0;
+// The sudt of the pool is swapped for CKB
+const SWAP_TO_CKB: u8 = 1;
+// CKB and the sudt are deposited for LP tokens
+const ADD_LIQUIDITY: u8 = 2;
+// LP tokens are burned for CKB and the sudt
+const REMOVE_LIQUIDITY: u8 = 3;
+
+struct Request {
+    sudt_amount: u128,
+    request_type: u8,
+    user_lock_hash: [u8; 32],
+    min_amount_out: u128,
+    min_capacity_out: u64,
+}
+
+fn read_u128(data: &[u8], offset: usize) -> u128 {
+    let mut buf = [0u8; 16];
+    buf.copy_from_slice(&data[offset..offset + 16]);
+    u128::from_le_bytes(buf)
+}
+
+fn parse_request(data: &[u8]) -> Result<Request, Error> {
+    if data.len() != REQUEST_DATA_LEN {
+        return Err(Error::WrongRequestData);
+    }
+    let mut request = Request {
+        sudt_amount: read_u128(data, 0),
+        request_type: data[16],
+        user_lock_hash: [0u8; 32],
+        min_amount_out: read_u128(data, 49),
+        min_capacity_out: 0,
+    };
+    request.user_lock_hash.copy_from_slice(&data[17..49]);
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&data[65..73]);
+    request.min_capacity_out = u64::from_le_bytes(buf);
+    Ok(request)
+}
+
+// The index of the pool cell in the inputs, None if the pool is not touched
+fn find_pool_input(pool_type_hash: &[u8]) -> Option<usize> {
+    QueryIter::new(load_cell_type_hash, Source::Input).position(|type_hash| {
+        type_hash.map_or(false, |type_hash| type_hash[..] == pool_type_hash[..])
+    })
+}
+
+fn load_sudt_amount(index: usize) -> Result<u128, Error> {
+    let data = load_cell_data(index, Source::Output)?;
+    if data.len() < SUDT_LEN {
+        return Err(Error::RequestNotFulfilled);
+    }
+    Ok(read_u128(&data, 0))
+}
+
+// The output gives back the request cell untouched, in case the pool can't fill it
+fn is_refunded(input_index: usize, request: &Request) -> Result<bool, Error> {
+    let type_hash = load_cell_type_hash(input_index, Source::Input)?;
+    if load_cell_type_hash(input_index, Source::Output)? != type_hash
+        || load_cell_capacity(input_index, Source::Output)?
+            < load_cell_capacity(input_index, Source::Input)?
+    {
+        return Ok(false);
+    }
+    if type_hash.is_some() && load_sudt_amount(input_index)? < request.sudt_amount {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// The output carries the asset the request asks for, which is no less than its minimum
+fn is_fulfilled(input_index: usize, pool_index: usize, request: &Request) -> Result<bool, Error> {
+    let pool_args = match load_cell_type(pool_index, Source::Input)? {
+        Some(script) => {
+            let args: Bytes = script.args().unpack();
+            parse_pool_args(&args)?
+        }
+        None => return Err(Error::PoolNotFound),
+    };
+
+    let sudt_pair = pool_args.is_sudt_pair();
+    let type_matched = match request.request_type {
+        // A cell can't carry the two sudts of a sUDT/sUDT pool, so its liquidity requests are
+        // only refunded
+        ADD_LIQUIDITY | REMOVE_LIQUIDITY if sudt_pair => false,
+        SWAP_TO_SUDT | REMOVE_LIQUIDITY => {
+            let type_hash = load_cell_type_hash(input_index, Source::Output)?;
+            type_hash.map_or(false, |type_hash| type_hash == pool_args.sudt_type_hash)
+        }
+        SWAP_TO_CKB if sudt_pair => {
+            let type_hash = load_cell_type_hash(input_index, Source::Output)?;
+            type_hash.map_or(false, |type_hash| type_hash == pool_args.base_type_hash)
+        }
+        SWAP_TO_CKB => load_cell_type_hash(input_index, Source::Output)?.is_none(),
+        ADD_LIQUIDITY => {
+            // The LP sudt is owned by the reserve lock, which is right after the pool cell
+            let reserve_lock_hash = load_cell_lock_hash(pool_index + 1, Source::Input)?;
+            match load_cell_type(input_index, Source::Output)? {
+                Some(script) => {
+                    let args: Bytes = script.args().unpack();
+                    script.code_hash().as_slice() == &pool_args.lp_code_hash[..]
+                        && script.hash_type().as_slice() == &[pool_args.lp_hash_type]
+                        && args[..] == reserve_lock_hash[..]
+                }
+                None => false,
+            }
+        }
+        _ => return Err(Error::WrongRequestData),
+    };
+    let capacity = load_cell_capacity(input_index, Source::Output)?;
+    if !type_matched || capacity < request.min_capacity_out {
+        return Ok(false);
+    }
+    if (request.request_type != SWAP_TO_CKB || sudt_pair)
+        && load_sudt_amount(input_index)? < request.min_amount_out
+    {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// Each request input is paid by the output of the same index, so two requests never share a cell
+fn validate_execution(pool_index: usize) -> Result<(), Error> {
+    let script_hash = load_script_hash()?;
+    for (index, lock_hash) in QueryIter::new(load_cell_lock_hash, Source::Input).enumerate() {
+        if lock_hash != script_hash {
+            continue;
+        }
+        let request = parse_request(&load_cell_data(index, Source::Input)?)?;
+        match load_cell_lock_hash(index, Source::Output) {
+            Ok(lock_hash) if lock_hash == request.user_lock_hash => {}
+            Ok(_) | Err(SysError::IndexOutOfBound) => return Err(Error::RequestNotFulfilled),
+            Err(err) => return Err(err.into()),
+        }
+        if !is_fulfilled(index, pool_index, &request)? && !is_refunded(index, &request)? {
+            return Err(Error::RequestNotFulfilled);
+        }
+    }
+    Ok(())
+}
+
+// A request cell is executed by an aggregator together with the pool, and the pool type script
+// checks the reserves, so this lock only makes sure every user gets their minimum output or
+// a full refund. Without the pool, the owner can cancel the request with a signature.
+pub fn main() -> Result<(), Error> {
+    let script = load_script()?;
+    let args: Bytes = script.args().unpack();
+    if args.len() != ARGS_LEN {
+        return Err(Error::Encoding);
+    }
+
+    match find_pool_input(&args[20..]) {
+        Some(pool_index) => validate_execution(pool_index),
+        None => signature::validate(),
+    }
+}
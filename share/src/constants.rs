@@ -1,4 +1,3 @@
 pub const SUDT_LEN: usize = 16;
 // real price * 10 ^ 10 = cell price data
-pub const PRICE_PARAM: f64 = 10000000000.0;
-pub const PRECISION_NUMBER: f64 = 0.0001;
+pub const PRICE_PARAM: u128 = 10_000_000_000;
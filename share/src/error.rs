@@ -21,6 +21,58 @@ pub enum Error {
     InputsAndOutputsAmountNotSame = 15,
     WrongSwapAmount,
     TypeHashNotSame,
+    WrongOrderVersion,
+    WrongOrderFlags,
+    WrongOrderPrice = 20,
+    WrongFeeRate,
+    WrongExpiry,
+    WrongOwnerLock,
+    OrderExpired,
+    DuplicateMatchedOutput = 25,
+    WrongMinFillAmount,
+    FillAmountTooSmall,
+    RemainingAmountTooSmall,
+    OrderNotFullyFilled,
+    PostOnlyOrderNotMaker = 30,
+    QuoteTypeHashNotSame,
+    WrongOrderId,
+    WrongTrigger,
+    OracleNotFound,
+    OrderNotTriggered = 35,
+    WrongUDTExtension,
+    UDTNotAccepted,
+    ActionMissing,
+    WrongAction,
+    ModifiedOrderNotSame = 40,
+    WrongClearingPrice,
+    ClearingPriceNotSame,
+    BatchNotConserved,
+    WrongMatcherConfig,
+    MatcherConfigNotFound = 45,
+    MatcherNotAuthorized,
+    WrongPoolData,
+    PoolCellMissing,
+    PoolCellNotUnique,
+    WrongReserveCell = 50,
+    ReserveNotMatch,
+    PoolInvariantBroken,
+    PoolNotFound,
+    WrongLPAmount,
+    LPSupplyNotMatch = 55,
+    WrongRequestData,
+    RequestNotFulfilled,
+    WrongProtocolFee,
+    TreasuryNotFound,
+    TimestampNotFound = 60,
+    WrongPriceAccumulator,
+    WrongPoolId,
+    PoolNotRegistered,
+    PairAlreadyRegistered,
+    WrongRegistryData = 65,
+    TakerNotFound,
+    BatchUDTNotSame,
+    WrongPoolLock,
+    PoolCapacityDecreased,
 }
 
 impl From<SysError> for Error {
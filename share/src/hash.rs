@@ -0,0 +1,18 @@
+use blake2b_ref::{Blake2b, Blake2bBuilder};
+
+pub fn new_blake2b() -> Blake2b {
+    Blake2bBuilder::new(32)
+        .personal(b"ckb-default-hash")
+        .build()
+}
+
+/// The ckb blake2b hash of the concatenated parts
+pub fn blake2b_256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    let mut hasher = new_blake2b();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize(&mut hash);
+    hash
+}
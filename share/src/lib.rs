@@ -5,6 +5,12 @@
 
 pub mod constants;
 
+pub mod hash;
+
+pub mod pool;
+
 pub mod signature;
 
 pub mod error;
+
+pub mod u256;
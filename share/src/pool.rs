@@ -0,0 +1,54 @@
+use crate::error::Error;
+
+/// The type args of a pool cell of liquidity-poll-contract, which the request lock reads too:
+/// pool_id([u8; 32]) + sudt_type_hash([u8; 32]) of the sudt traded against CKB
+/// + lp_code_hash([u8; 32]) + lp_hash_type(u8) of the sudt script of the LP token, as in the
+/// header of the registry
+/// + registry_type_hash([u8; 32]) of the pair registry the pool is created in
+pub const POOL_ARGS_LEN: usize = 129;
+/// A sUDT/sUDT pool appends base_type_hash([u8; 32]) of the sudt which takes the place of CKB
+pub const SUDT_PAIR_POOL_ARGS_LEN: usize = 161;
+/// CKB is not a sudt, it takes the zero hash in a pair
+pub const CKB_TYPE_HASH: [u8; 32] = [0u8; 32];
+
+pub struct PoolArgs {
+    pub pool_id: [u8; 32],
+    pub sudt_type_hash: [u8; 32],
+    pub lp_code_hash: [u8; 32],
+    pub lp_hash_type: u8,
+    pub registry_type_hash: [u8; 32],
+    /// CKB_TYPE_HASH unless the pool is a sUDT/sUDT pool
+    pub base_type_hash: [u8; 32],
+}
+
+impl PoolArgs {
+    pub fn is_sudt_pair(&self) -> bool {
+        self.base_type_hash != CKB_TYPE_HASH
+    }
+}
+
+pub fn parse_pool_args(args: &[u8]) -> Result<PoolArgs, Error> {
+    if args.len() != POOL_ARGS_LEN && args.len() != SUDT_PAIR_POOL_ARGS_LEN {
+        return Err(Error::Encoding);
+    }
+    let mut pool_args = PoolArgs {
+        pool_id: [0u8; 32],
+        sudt_type_hash: [0u8; 32],
+        lp_code_hash: [0u8; 32],
+        lp_hash_type: args[96],
+        registry_type_hash: [0u8; 32],
+        base_type_hash: CKB_TYPE_HASH,
+    };
+    pool_args.pool_id.copy_from_slice(&args[0..32]);
+    pool_args.sudt_type_hash.copy_from_slice(&args[32..64]);
+    pool_args.lp_code_hash.copy_from_slice(&args[64..96]);
+    pool_args.registry_type_hash.copy_from_slice(&args[97..129]);
+    if args.len() == SUDT_PAIR_POOL_ARGS_LEN {
+        pool_args.base_type_hash.copy_from_slice(&args[129..161]);
+        // The two sudts of a pair are different, and neither of them is CKB
+        if !pool_args.is_sudt_pair() || pool_args.base_type_hash == pool_args.sudt_type_hash {
+            return Err(Error::Encoding);
+        }
+    }
+    Ok(pool_args)
+}
@@ -12,16 +12,10 @@ use ckb_std::{
     high_level::{load_script, load_witness_args},
 };
 
-use blake2b_ref::{Blake2b, Blake2bBuilder};
 use ckb_lib_secp256k1::LibSecp256k1;
 
 use crate::error::Error;
-
-fn new_blake2b() -> Blake2b {
-    Blake2bBuilder::new(32)
-        .personal(b"ckb-default-hash")
-        .build()
-}
+use crate::hash::new_blake2b;
 
 fn test_validate_blake2b_sighash_all(
     lib: &LibSecp256k1,
@@ -45,9 +39,12 @@ pub fn validate() -> Result<(), Error> {
     let script = load_script()?;
     let args: Bytes = script.args().unpack();
 
-    if args.len() != 20 {
+    // pubkey_hash(20 bytes), optionally followed by a 32-byte hash the lock is bound to, e.g.
+    // the udt type hash of a market order or the pool type hash of a liquidity request
+    if args.len() != 20 && args.len() != 52 {
         return Err(Error::Encoding);
     }
+    let pubkey_hash = &args[..20];
 
     let witness_args = load_witness_args(0, Source::GroupInput)?;
 
@@ -56,7 +53,7 @@ pub fn validate() -> Result<(), Error> {
     let lib = LibSecp256k1::load(&mut context);
 
     if witness_args.input_type().to_opt().is_none() {
-        test_validate_blake2b_sighash_all(&lib, &args)?;
+        test_validate_blake2b_sighash_all(&lib, pubkey_hash)?;
     } else {
         let witness: Bytes = witness_args
             .input_type()
@@ -81,14 +78,14 @@ pub fn validate() -> Result<(), Error> {
                 // debug!("recover pubkey error: {}", err);
                 Error::RecoverPubkey
             })?;
-        let pubkey_hash = {
+        let recovered_pubkey_hash = {
             let mut buf = [0u8; 32];
             let mut hasher = new_blake2b();
             hasher.update(pubkey.as_slice());
             hasher.finalize(&mut buf);
             buf
         };
-        if &args[..] != &pubkey_hash[..20] {
+        if pubkey_hash != &recovered_pubkey_hash[..20] {
             return Err(Error::WrongPubkey);
         }
     }
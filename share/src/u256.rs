@@ -0,0 +1,140 @@
+use core::cmp::Ordering;
+
+const LOW_MASK: u128 = u64::MAX as u128;
+
+/// Unsigned 256 bits integer which is wide enough to hold the product of two u128 numbers,
+/// so that amount * price * fee can be computed without overflow and without floating point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct U256 {
+    hi: u128,
+    lo: u128,
+}
+
+impl U256 {
+    pub const fn zero() -> Self {
+        U256 { hi: 0, lo: 0 }
+    }
+
+    /// a * b without overflow
+    pub fn mul(a: u128, b: u128) -> Self {
+        let (a_hi, a_lo) = (a >> 64, a & LOW_MASK);
+        let (b_hi, b_lo) = (b >> 64, b & LOW_MASK);
+
+        let lo_lo = a_lo * b_lo;
+        let lo_hi = a_lo * b_hi;
+        let hi_lo = a_hi * b_lo;
+        let hi_hi = a_hi * b_hi;
+
+        // at most 3 * (2^64 - 1), no overflow
+        let middle = (lo_lo >> 64) + (lo_hi & LOW_MASK) + (hi_lo & LOW_MASK);
+        U256 {
+            hi: hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (middle >> 64),
+            lo: (lo_lo & LOW_MASK) | (middle << 64),
+        }
+    }
+
+    /// self / divisor and self % divisor, the divisor must not be zero
+    pub fn div_rem(self, divisor: u128) -> (Self, u128) {
+        assert!(divisor != 0, "divide by zero");
+        let mut quotient = U256::zero();
+        let mut remainder = 0u128;
+        for bit in (0..256).rev() {
+            // the real remainder is carry * 2^128 + remainder, which is always less than 2 * divisor
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | self.bit(bit);
+            if carry == 1 || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.set_bit(bit);
+            }
+        }
+        (quotient, remainder)
+    }
+
+    /// floor(self / divisor)
+    pub fn div_floor(self, divisor: u128) -> Self {
+        self.div_rem(divisor).0
+    }
+
+    /// ceil(self / divisor)
+    pub fn div_ceil(self, divisor: u128) -> Self {
+        let (quotient, remainder) = self.div_rem(divisor);
+        if remainder == 0 {
+            quotient
+        } else {
+            // quotient < 2^256 / divisor, adding one can not overflow when divisor > 1
+            quotient.add_one()
+        }
+    }
+
+    /// floor(sqrt(self)), which is always less than 2^128
+    pub fn sqrt(self) -> u128 {
+        let mut root = 0u128;
+        for bit in (0..128).rev() {
+            let candidate = root | (1 << bit);
+            if U256::mul(candidate, candidate) <= self {
+                root = candidate;
+            }
+        }
+        root
+    }
+
+    /// self as u128, None if it doesn't fit
+    pub fn to_u128(self) -> Option<u128> {
+        if self.hi == 0 {
+            Some(self.lo)
+        } else {
+            None
+        }
+    }
+
+    fn add_one(self) -> Self {
+        let (lo, carry) = self.lo.overflowing_add(1);
+        U256 {
+            hi: self.hi + carry as u128,
+            lo,
+        }
+    }
+
+    fn bit(&self, index: usize) -> u128 {
+        if index >= 128 {
+            (self.hi >> (index - 128)) & 1
+        } else {
+            (self.lo >> index) & 1
+        }
+    }
+
+    fn set_bit(&mut self, index: usize) {
+        if index >= 128 {
+            self.hi |= 1 << (index - 128);
+        } else {
+            self.lo |= 1 << index;
+        }
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        U256 { hi: 0, lo: value }
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        U256 {
+            hi: 0,
+            lo: value as u128,
+        }
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hi.cmp(&other.hi).then(self.lo.cmp(&other.lo))
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
@@ -10,6 +10,9 @@ mod order_book_tests;
 #[cfg(test)]
 mod liquidity_poll_tests;
 
+#[cfg(test)]
+mod liquidity_request_tests;
+
 const TEST_ENV_VAR: &str = "CAPSULE_TEST_ENV";
 
 pub enum TestEnv {
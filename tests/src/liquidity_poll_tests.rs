@@ -1,65 +1,498 @@
 use super::*;
-use ckb_testtool::context::Context;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
+use ckb_tool::ckb_error::assert_error_eq;
+use ckb_tool::ckb_hash::new_blake2b;
+use ckb_tool::ckb_script::ScriptError;
 use ckb_tool::ckb_types::{
     bytes::Bytes,
-    core::TransactionBuilder,
+    core::{Capacity, HeaderBuilder, TransactionBuilder, TransactionView},
     packed::*,
     prelude::*,
 };
-use ckb_tool::ckb_error::assert_error_eq;
-use ckb_tool::ckb_script::ScriptError;
 
-const MAX_CYCLES: u64 = 10_000_000;
+pub(crate) const MAX_CYCLES: u64 = 10_000_000;
 
-// error numbers
-const ERROR_EMPTY_ARGS: i8 = 5;
+// The pool holds 10000 CKB and 10000 sudt before each swap
+pub(crate) const CKB_RESERVE: u128 = 1_000_000_000_000;
+pub(crate) const SUDT_RESERVE: u128 = 1_000_000_000_000;
+// 100 CKB or 100 sudt are swapped in, and 98.71580343 of the other asset can be taken out at most
+pub(crate) const AMOUNT_IN: u128 = 10_000_000_000;
+pub(crate) const MAX_AMOUNT_OUT: u128 = 9_871_580_343;
+// sqrt(CKB_RESERVE * SUDT_RESERVE)
+pub(crate) const LP_SUPPLY: u128 = 1_000_000_000_000;
+const MINIMUM_LIQUIDITY: u128 = 1_000;
+// The pool is last updated at the header dep of each transaction, in milliseconds
+pub(crate) const POOL_TIMESTAMP: u64 = 1_600_000_000_000;
 
-#[test]
-fn test_success() {
-    // deploy contract
-    let mut context = Context::default();
-    let contract_bin: Bytes = Loader::default().load_binary("liquidity-poll-contract");
-    let out_point = context.deploy_cell(contract_bin);
+pub(crate) struct PoolScripts {
+    pub(crate) pool_type: Script,
+    pub(crate) reserve_lock: Script,
+    pub(crate) sudt_type: Script,
+    pub(crate) lp_type: Script,
+    pub(crate) registry_type: Script,
+    pub(crate) user_lock: Script,
+    pub(crate) cell_deps: Vec<CellDep>,
+    // the sudt in the place of CKB of a sUDT/sUDT pool
+    pub(crate) base_type: Option<Script>,
+}
+
+pub(crate) fn deploy_pool_scripts(context: &mut Context) -> PoolScripts {
+    let pool_bin: Bytes = Loader::default().load_binary("liquidity-poll-contract");
+    let pool_out_point = context.deploy_cell(pool_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
 
-    // prepare scripts
-    let lock_script = context
-        .build_script(&out_point, Bytes::from(vec![42]))
+    // a mock sudt, and the anyone can spend lock of the pool cell and the user cells
+    let sudt_type = context
+        .build_script(&always_success_out_point, Bytes::from(vec![1]))
+        .expect("script");
+    let user_lock = context
+        .build_script(&always_success_out_point, Bytes::new())
+        .expect("script");
+    let registry_type = context
+        .build_script(&pool_out_point, Bytes::from(vec![7u8; 32]))
+        .expect("script");
+    // a zero pool id + the sudt type hash + the mock sudt script of the LP token + the registry
+    let mut pool_args = vec![0u8; 32];
+    pool_args.extend_from_slice(sudt_type.calc_script_hash().as_slice());
+    pool_args.extend_from_slice(sudt_type.code_hash().as_slice());
+    pool_args.extend_from_slice(sudt_type.hash_type().as_slice());
+    pool_args.extend_from_slice(registry_type.calc_script_hash().as_slice());
+    let pool_type = context
+        .build_script(&pool_out_point, Bytes::from(pool_args))
+        .expect("script");
+    let reserve_lock = context
+        .build_script(&pool_out_point, pool_type.calc_script_hash().raw_data())
+        .expect("script");
+    let lp_type = context
+        .build_script(&always_success_out_point, reserve_lock.calc_script_hash().raw_data())
         .expect("script");
-    let lock_script_dep = CellDep::new_builder()
-        .out_point(out_point)
-        .build();
 
-    // prepare cells
-    let input_out_point = context.create_cell(
-        CellOutput::new_builder()
-            .capacity(1000u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        Bytes::new(),
-    );
-    let input = CellInput::new_builder()
-        .previous_output(input_out_point)
-        .build();
-    let outputs = vec![
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script)
+    let cell_deps = vec![
+        CellDep::new_builder().out_point(pool_out_point).build(),
+        CellDep::new_builder()
+            .out_point(always_success_out_point)
             .build(),
     ];
+    PoolScripts {
+        pool_type,
+        reserve_lock,
+        sudt_type,
+        lp_type,
+        registry_type,
+        user_lock,
+        cell_deps,
+        base_type: None,
+    }
+}
+
+fn blake2b_256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    for part in parts {
+        blake2b.update(part);
+    }
+    blake2b.finalize(&mut hash);
+    hash
+}
+
+fn type_hash(script: &Script) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(script.calc_script_hash().as_slice());
+    hash
+}
+
+// The type hashes of the pair in ascending order, where CKB takes the zero hash
+fn sorted_pair(scripts: &PoolScripts) -> ([u8; 32], [u8; 32]) {
+    let base_type_hash = scripts.base_type.as_ref().map_or([0u8; 32], type_hash);
+    let sudt_type_hash = type_hash(&scripts.sudt_type);
+    if base_type_hash <= sudt_type_hash {
+        (base_type_hash, sudt_type_hash)
+    } else {
+        (sudt_type_hash, base_type_hash)
+    }
+}
+
+fn pair_hash(scripts: &PoolScripts) -> [u8; 32] {
+    let (lower_hash, higher_hash) = sorted_pair(scripts);
+    blake2b_256(&[&lower_hash, &higher_hash])
+}
+
+// The pool scripts with pool_args, and the reserve lock and the LP token of the new pool
+fn set_pool_args(scripts: &mut PoolScripts, pool_args: Vec<u8>) {
+    scripts.pool_type = scripts
+        .pool_type
+        .clone()
+        .as_builder()
+        .args(Bytes::from(pool_args).pack())
+        .build();
+    scripts.reserve_lock = scripts
+        .reserve_lock
+        .clone()
+        .as_builder()
+        .args(scripts.pool_type.calc_script_hash().as_bytes().pack())
+        .build();
+    scripts.lp_type = scripts
+        .lp_type
+        .clone()
+        .as_builder()
+        .args(scripts.reserve_lock.calc_script_hash().as_bytes().pack())
+        .build();
+}
+
+// The pool scripts with the pool id derived from out_point
+fn set_pool_id(scripts: &mut PoolScripts, out_point: &OutPoint) {
+    let (lower_hash, higher_hash) = sorted_pair(scripts);
+    let pool_id = blake2b_256(&[out_point.as_slice(), &lower_hash, &higher_hash]);
+    let pool_args: Bytes = scripts.pool_type.args().unpack();
+    let mut pool_args = pool_args.to_vec();
+    pool_args[..32].copy_from_slice(&pool_id);
+    set_pool_args(scripts, pool_args);
+}
+
+// The pool scripts registered in registry_type instead
+fn set_registry(scripts: &mut PoolScripts, registry_type: Script) {
+    let pool_args: Bytes = scripts.pool_type.args().unpack();
+    let mut pool_args = pool_args.to_vec();
+    pool_args[97..129].copy_from_slice(registry_type.calc_script_hash().as_slice());
+    set_pool_args(scripts, pool_args);
+    scripts.registry_type = registry_type;
+}
+
+// The pool scripts of a sUDT/sUDT pool, where another mock sudt takes the place of CKB
+pub(crate) fn set_sudt_pair(scripts: &mut PoolScripts) {
+    let base_type = scripts
+        .sudt_type
+        .clone()
+        .as_builder()
+        .args(Bytes::from(vec![2]).pack())
+        .build();
+    let pool_args: Bytes = scripts.pool_type.args().unpack();
+    let mut pool_args = pool_args.to_vec();
+    pool_args.extend_from_slice(base_type.calc_script_hash().as_slice());
+    set_pool_args(scripts, pool_args);
+    scripts.base_type = Some(base_type);
+}
+
+// The fields of the pool data
+struct PoolState {
+    ckb_reserve: u128,
+    sudt_reserve: u128,
+    lp_supply: u128,
+    protocol_fees: (u128, u128),
+    protocol_fee_share: u16,
+    treasury_lock_hash: [u8; 32],
+    amplification: u64,
+    price_cumulatives: (u128, u128),
+    last_timestamp: u64,
+    multipliers: (u64, u64),
+}
+
+impl PoolState {
+    // A constant-product pool without protocol fee, which is last updated at POOL_TIMESTAMP
+    fn new(ckb_reserve: u128, sudt_reserve: u128, lp_supply: u128) -> Self {
+        PoolState {
+            ckb_reserve,
+            sudt_reserve,
+            lp_supply,
+            protocol_fees: (0, 0),
+            protocol_fee_share: 0,
+            treasury_lock_hash: [0u8; 32],
+            amplification: 0,
+            price_cumulatives: (0, 0),
+            last_timestamp: POOL_TIMESTAMP,
+            multipliers: (1, 1),
+        }
+    }
+
+    fn data(&self) -> Bytes {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.ckb_reserve.to_le_bytes());
+        data.extend_from_slice(&self.sudt_reserve.to_le_bytes());
+        data.extend_from_slice(&self.lp_supply.to_le_bytes());
+        data.extend_from_slice(&self.protocol_fees.0.to_le_bytes());
+        data.extend_from_slice(&self.protocol_fees.1.to_le_bytes());
+        data.extend_from_slice(&self.protocol_fee_share.to_le_bytes());
+        data.extend_from_slice(&self.treasury_lock_hash);
+        data.extend_from_slice(&self.amplification.to_le_bytes());
+        data.extend_from_slice(&self.price_cumulatives.0.to_le_bytes());
+        data.extend_from_slice(&self.price_cumulatives.1.to_le_bytes());
+        data.extend_from_slice(&self.last_timestamp.to_le_bytes());
+        data.extend_from_slice(&self.multipliers.0.to_le_bytes());
+        data.extend_from_slice(&self.multipliers.1.to_le_bytes());
+        Bytes::from(data)
+    }
+}
+
+pub(crate) fn pool_data(ckb_reserve: u128, sudt_reserve: u128, lp_supply: u128) -> Bytes {
+    PoolState::new(ckb_reserve, sudt_reserve, lp_supply).data()
+}
 
-    let outputs_data = vec![Bytes::new(); 2];
+pub(crate) fn sudt_data(sudt_amount: u128) -> Bytes {
+    Bytes::from(sudt_amount.to_le_bytes().to_vec())
+}
+
+// The capacity of the pool cell is its occupied capacity plus the ckb reserve, none in a sUDT/sUDT
+// pool
+pub(crate) fn pool_cell(scripts: &PoolScripts, ckb_reserve: u128, data: &Bytes) -> CellOutput {
+    let ckb_reserve = if scripts.base_type.is_some() {
+        0
+    } else {
+        ckb_reserve
+    };
+    let cell = CellOutput::new_builder()
+        .lock(scripts.user_lock.clone())
+        .type_(Some(scripts.pool_type.clone()).pack())
+        .build();
+    let occupied_capacity = cell
+        .occupied_capacity(Capacity::bytes(data.len()).unwrap())
+        .unwrap()
+        .as_u64();
+    cell.as_builder()
+        .capacity((occupied_capacity + ckb_reserve as u64).pack())
+        .build()
+}
+
+pub(crate) fn reserve_cell(scripts: &PoolScripts) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(20_000_000_000u64.pack())
+        .lock(scripts.reserve_lock.clone())
+        .type_(Some(scripts.sudt_type.clone()).pack())
+        .build()
+}
+
+// The reserve cell of the base sudt of a sUDT/sUDT pool, right after the sudt reserve cell
+pub(crate) fn base_reserve_cell(scripts: &PoolScripts) -> CellOutput {
+    reserve_cell(scripts)
+        .as_builder()
+        .type_(scripts.base_type.clone().pack())
+        .build()
+}
+
+// A plain cell of the reserve lock, which enables the owner mode of the LP sudt
+fn seed_cell(scripts: &PoolScripts) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(20_000_000_000u64.pack())
+        .lock(scripts.reserve_lock.clone())
+        .build()
+}
+
+fn lp_cell(scripts: &PoolScripts) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(20_000_000_000u64.pack())
+        .lock(scripts.user_lock.clone())
+        .type_(Some(scripts.lp_type.clone()).pack())
+        .build()
+}
+
+fn user_cell(scripts: &PoolScripts, capacity: u64, with_sudt: bool) -> CellOutput {
+    let type_ = if with_sudt {
+        Some(scripts.sudt_type.clone())
+    } else {
+        None
+    };
+    CellOutput::new_builder()
+        .capacity(capacity.pack())
+        .lock(scripts.user_lock.clone())
+        .type_(type_.pack())
+        .build()
+}
+
+pub(crate) fn timestamp_header_dep(context: &mut Context, timestamp: u64) -> Byte32 {
+    let header = HeaderBuilder::default()
+        .timestamp(timestamp.pack())
+        .build();
+    context.insert_header(header.clone());
+    header.hash()
+}
 
-    // build transaction
-    let tx = TransactionBuilder::default()
-        .input(input)
+fn build_tx(
+    context: &mut Context,
+    scripts: &PoolScripts,
+    inputs: Vec<OutPoint>,
+    outputs: Vec<CellOutput>,
+    outputs_data: Vec<Bytes>,
+) -> TransactionView {
+    TransactionBuilder::default()
+        .inputs(inputs.into_iter().map(|out_point| {
+            CellInput::new_builder()
+                .previous_output(out_point)
+                .build()
+        }))
         .outputs(outputs)
         .outputs_data(outputs_data.pack())
-        .cell_dep(lock_script_dep)
-        .build();
+        .cell_deps(scripts.cell_deps.clone())
+        .header_dep(timestamp_header_dep(context, POOL_TIMESTAMP))
+        .build()
+}
+
+// Spends the pool of CKB_RESERVE, SUDT_RESERVE and LP_SUPPLY with a user cell and the LP tokens
+// lp_in of the user, the new pool cell and the LP tokens lp_out are given by the caller
+fn build_pool_update_context(
+    output_ckb_reserve: u128,
+    output_sudt_reserve: u128,
+    output_pool_data: Bytes,
+    lp_in: u128,
+    lp_out: u128,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+
+    let input_pool_data = pool_data(CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY);
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, CKB_RESERVE, &input_pool_data),
+        input_pool_data,
+    );
+    let reserve_out_point = context.create_cell(reserve_cell(&scripts), sudt_data(SUDT_RESERVE));
+    let user_out_point = context.create_cell(
+        user_cell(&scripts, 200_000_000_000, true),
+        sudt_data(AMOUNT_IN),
+    );
+
+    let mut inputs = vec![pool_out_point, reserve_out_point, user_out_point];
+    if lp_in > 0 {
+        inputs.push(context.create_cell(lp_cell(&scripts), sudt_data(lp_in)));
+    }
+
+    let mut outputs = vec![
+        pool_cell(&scripts, output_ckb_reserve, &output_pool_data),
+        reserve_cell(&scripts),
+        user_cell(&scripts, 100_000_000_000, true),
+    ];
+    let mut outputs_data = vec![
+        output_pool_data,
+        sudt_data(output_sudt_reserve),
+        sudt_data(MAX_AMOUNT_OUT),
+    ];
+    if lp_out > 0 {
+        outputs.push(lp_cell(&scripts));
+        outputs_data.push(sudt_data(lp_out));
+    }
+    let tx = build_tx(&mut context, &scripts, inputs, outputs, outputs_data);
+    (context, tx)
+}
+
+fn build_swap_context(
+    output_ckb_reserve: u128,
+    output_sudt_reserve: u128,
+    output_pool_data: Bytes,
+) -> (Context, TransactionView) {
+    build_pool_update_context(output_ckb_reserve, output_sudt_reserve, output_pool_data, 0, 0)
+}
+
+fn build_ckb_to_sudt_swap_context(amount_out: u128) -> (Context, TransactionView) {
+    let ckb_reserve = CKB_RESERVE + AMOUNT_IN;
+    let sudt_reserve = SUDT_RESERVE - amount_out;
+    build_swap_context(
+        ckb_reserve,
+        sudt_reserve,
+        pool_data(ckb_reserve, sudt_reserve, LP_SUPPLY),
+    )
+}
+
+// The first deposit of CKB_RESERVE and SUDT_RESERVE, which mints lp_minted of lp_supply
+// to the creator
+fn build_creation_context(lp_supply: u128, lp_minted: u128) -> (Context, TransactionView) {
+    build_registered_creation_context(lp_supply, lp_minted, 0, Bytes::new(), true)
+}
+
+// The registry pins the LP token script of the pools, the mock sudt, and their treasury
+fn registry_header(scripts: &PoolScripts) -> Vec<u8> {
+    let mut header = scripts.sudt_type.code_hash().as_slice().to_vec();
+    header.extend_from_slice(scripts.sudt_type.hash_type().as_slice());
+    header.extend_from_slice(&treasury_lock_hash(scripts));
+    header
+}
+
+fn registry_cell(scripts: &PoolScripts) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(100_000_000_000u64.pack())
+        .lock(scripts.user_lock.clone())
+        .type_(Some(scripts.registry_type.clone()).pack())
+        .build()
+}
+
+// Like build_creation_context, but the pool id is derived from the input of id_input_index, and
+// the pool is appended to the registry entries if registered
+fn build_registered_creation_context(
+    lp_supply: u128,
+    lp_minted: u128,
+    id_input_index: usize,
+    registry_entries: Bytes,
+    registered: bool,
+) -> (Context, TransactionView) {
+    build_custom_creation_context(
+        |scripts| registry_header(scripts),
+        lp_supply,
+        lp_minted,
+        id_input_index,
+        registry_entries,
+        registered,
+    )
+}
+
+// Like build_registered_creation_context, but the pool scripts are set up by setup, which gives
+// the header of the registry
+fn build_custom_creation_context(
+    setup: fn(&mut PoolScripts) -> Vec<u8>,
+    lp_supply: u128,
+    lp_minted: u128,
+    id_input_index: usize,
+    registry_entries: Bytes,
+    registered: bool,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let mut registry_data = setup(&mut scripts);
+    registry_data.extend_from_slice(&registry_entries);
+    let user_out_point =
+        context.create_cell(user_cell(&scripts, 2_000_000_000_000, false), Bytes::new());
+    let other_out_point =
+        context.create_cell(user_cell(&scripts, 20_000_000_000, false), Bytes::new());
+    let id_out_point = [&user_out_point, &other_out_point][id_input_index];
+    set_pool_id(&mut scripts, id_out_point);
+    let seed_out_point = context.create_cell(seed_cell(&scripts), Bytes::new());
+    let registry_out_point =
+        context.create_cell(registry_cell(&scripts), Bytes::from(registry_data.clone()));
+
+    let mut output_registry_data = registry_data;
+    if registered {
+        output_registry_data.extend_from_slice(&pair_hash(&scripts));
+        output_registry_data.extend_from_slice(scripts.pool_type.calc_script_hash().as_slice());
+    }
+    let data = PoolState {
+        treasury_lock_hash: treasury_lock_hash(&scripts),
+        ..PoolState::new(CKB_RESERVE, SUDT_RESERVE, lp_supply)
+    }
+    .data();
+    let mut outputs = vec![
+        pool_cell(&scripts, CKB_RESERVE, &data),
+        reserve_cell(&scripts),
+    ];
+    let mut outputs_data = vec![data, sudt_data(SUDT_RESERVE)];
+    if scripts.base_type.is_some() {
+        outputs.push(base_reserve_cell(&scripts));
+        outputs_data.push(sudt_data(CKB_RESERVE));
+    }
+    outputs.extend(vec![lp_cell(&scripts), registry_cell(&scripts)]);
+    outputs_data.extend(vec![sudt_data(lp_minted), Bytes::from(output_registry_data)]);
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![
+            user_out_point,
+            other_out_point,
+            seed_out_point,
+            registry_out_point,
+        ],
+        outputs,
+        outputs_data,
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_create_pool() {
+    let (mut context, tx) = build_creation_context(LP_SUPPLY, LP_SUPPLY - MINIMUM_LIQUIDITY);
     let tx = context.complete_tx(tx);
 
     // run
@@ -70,61 +503,1286 @@ fn test_success() {
 }
 
 #[test]
-fn test_empty_args() {
-    // deploy contract
+fn test_create_pool_without_reserve_cell() {
     let mut context = Context::default();
-    let contract_bin: Bytes = Loader::default().load_binary("liquidity-poll-contract");
-    let out_point = context.deploy_cell(contract_bin);
+    let scripts = deploy_pool_scripts(&mut context);
+    let user_out_point =
+        context.create_cell(user_cell(&scripts, 2_000_000_000_000, false), Bytes::new());
 
-    // prepare scripts
-    let lock_script = context
-        .build_script(&out_point, Default::default())
-        .expect("script");
-    let lock_script_dep = CellDep::new_builder()
-        .out_point(out_point)
-        .build();
+    let data = pool_data(CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY);
+    let outputs = vec![pool_cell(&scripts, CKB_RESERVE, &data)];
+    let tx = build_tx(&mut context, &scripts, vec![user_out_point], outputs, vec![data]);
+    let tx = context.complete_tx(tx);
 
-    // prepare cells
-    let input_out_point = context.create_cell(
-        CellOutput::new_builder()
-            .capacity(1000u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        Bytes::new(),
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(50).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_ckb_for_sudt() {
+    let (mut context, tx) = build_ckb_to_sudt_swap_context(MAX_AMOUNT_OUT);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_swap_ckb_for_too_much_sudt() {
+    let (mut context, tx) = build_ckb_to_sudt_swap_context(MAX_AMOUNT_OUT + 1);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(52).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_sudt_for_ckb() {
+    let ckb_reserve = CKB_RESERVE - MAX_AMOUNT_OUT;
+    let sudt_reserve = SUDT_RESERVE + AMOUNT_IN;
+    let (mut context, tx) = build_swap_context(
+        ckb_reserve,
+        sudt_reserve,
+        pool_data(ckb_reserve, sudt_reserve, LP_SUPPLY),
     );
-    let input = CellInput::new_builder()
-        .previous_output(input_out_point)
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_swap_reserve_not_match() {
+    // the sudt is taken out of the reserve cell, but the pool data is not updated
+    let ckb_reserve = CKB_RESERVE + AMOUNT_IN;
+    let (mut context, tx) = build_swap_context(
+        ckb_reserve,
+        SUDT_RESERVE - MAX_AMOUNT_OUT,
+        pool_data(ckb_reserve, SUDT_RESERVE, LP_SUPPLY),
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(51).input_type_script(script_cell_index)
+    );
+}
+
+// Replaces the output at index of the transaction
+fn with_output(tx: TransactionView, index: usize, output: CellOutput) -> TransactionView {
+    let mut outputs: Vec<CellOutput> = tx.outputs().into_iter().collect();
+    outputs[index] = output;
+    tx.as_advanced_builder().set_outputs(outputs).build()
+}
+
+#[test]
+fn test_swap_changing_pool_lock() {
+    let (mut context, tx) = build_ckb_to_sudt_swap_context(MAX_AMOUNT_OUT);
+    // the pool cell is handed over to a lock of the same size
+    let pool_output = tx.output(0).unwrap();
+    let lock = pool_output
+        .lock()
+        .as_builder()
+        .code_hash([9u8; 32].pack())
         .build();
-    let outputs = vec![
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script.clone())
-            .build(),
-        CellOutput::new_builder()
-            .capacity(500u64.pack())
-            .lock(lock_script)
+    let tx = with_output(tx, 0, pool_output.as_builder().lock(lock).build());
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(68).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_draining_reserve_capacity() {
+    let (mut context, tx) = build_ckb_to_sudt_swap_context(MAX_AMOUNT_OUT);
+    // 50 CKB are taken out of the reserve cell
+    let reserve_output = tx.output(1).unwrap();
+    let tx = with_output(
+        tx,
+        1,
+        reserve_output
+            .as_builder()
+            .capacity(15_000_000_000u64.pack())
             .build(),
-    ];
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(69).input_type_script(script_cell_index)
+    );
+}
 
-    let outputs_data = vec![Bytes::new(); 2];
+#[test]
+fn test_destroy_pool() {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+    let input_pool_data = pool_data(CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY);
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, CKB_RESERVE, &input_pool_data),
+        input_pool_data,
+    );
+    let reserve_out_point = context.create_cell(reserve_cell(&scripts), sudt_data(SUDT_RESERVE));
 
-    // build transaction
-    let tx = TransactionBuilder::default()
-        .input(input)
-        .outputs(outputs)
-        .outputs_data(outputs_data.pack())
-        .cell_dep(lock_script_dep)
-        .build();
+    let outputs = vec![user_cell(&scripts, 1_000_000_000_000, true)];
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![pool_out_point, reserve_out_point],
+        outputs,
+        vec![sudt_data(SUDT_RESERVE)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(48).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_spend_reserve_without_pool() {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+    let reserve_out_point = context.create_cell(reserve_cell(&scripts), sudt_data(SUDT_RESERVE));
+
+    let outputs = vec![user_cell(&scripts, 20_000_000_000, true)];
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![reserve_out_point],
+        outputs,
+        vec![sudt_data(SUDT_RESERVE)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(53).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_minting_minimum_liquidity() {
+    let (mut context, tx) = build_creation_context(LP_SUPPLY, LP_SUPPLY);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(55).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_with_wrong_lp_supply() {
+    let lp_supply = LP_SUPPLY * 2;
+    let (mut context, tx) = build_creation_context(lp_supply, lp_supply - MINIMUM_LIQUIDITY);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(54).output_type_script(script_cell_index)
+    );
+}
+
+// 100 CKB and 100 sudt are deposited for 1% of the LP supply
+fn build_deposit_context(lp_minted: u128) -> (Context, TransactionView) {
+    let ckb_reserve = CKB_RESERVE + AMOUNT_IN;
+    let sudt_reserve = SUDT_RESERVE + AMOUNT_IN;
+    let lp_supply = LP_SUPPLY + lp_minted;
+    build_pool_update_context(
+        ckb_reserve,
+        sudt_reserve,
+        pool_data(ckb_reserve, sudt_reserve, lp_supply),
+        0,
+        lp_minted,
+    )
+}
+
+#[test]
+fn test_add_liquidity() {
+    let (mut context, tx) = build_deposit_context(LP_SUPPLY / 100);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_add_liquidity_minting_too_much() {
+    let (mut context, tx) = build_deposit_context(LP_SUPPLY / 100 + 1);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(54).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_add_liquidity_without_lp_tokens() {
+    // the pool data claims the minted LP tokens, which are not paid to anyone
+    let ckb_reserve = CKB_RESERVE + AMOUNT_IN;
+    let sudt_reserve = SUDT_RESERVE + AMOUNT_IN;
+    let (mut context, tx) = build_pool_update_context(
+        ckb_reserve,
+        sudt_reserve,
+        pool_data(ckb_reserve, sudt_reserve, LP_SUPPLY + LP_SUPPLY / 100),
+        0,
+        0,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(55).input_type_script(script_cell_index)
+    );
+}
+
+// 1% of the LP supply is burned for 100 CKB and the given sudt
+fn build_withdrawal_context(sudt_out: u128) -> (Context, TransactionView) {
+    let ckb_reserve = CKB_RESERVE - AMOUNT_IN;
+    let sudt_reserve = SUDT_RESERVE - sudt_out;
+    let lp_burned = LP_SUPPLY / 100;
+    build_pool_update_context(
+        ckb_reserve,
+        sudt_reserve,
+        pool_data(ckb_reserve, sudt_reserve, LP_SUPPLY - lp_burned),
+        lp_burned,
+        0,
+    )
+}
+
+#[test]
+fn test_remove_liquidity() {
+    let (mut context, tx) = build_withdrawal_context(AMOUNT_IN);
     let tx = context.complete_tx(tx);
 
     // run
-    let err = context
+    let cycles = context
         .verify_tx(&tx, MAX_CYCLES)
-        .unwrap_err();
-    // we expect an error raised from 0-indexed cell's lock script
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_remove_too_much_liquidity() {
+    let (mut context, tx) = build_withdrawal_context(AMOUNT_IN + 1);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(54).input_type_script(script_cell_index)
+    );
+}
+
+// 20% of the swap fee goes to the treasury
+const PROTOCOL_FEE_SHARE: u16 = 2_000;
+// 100 CKB * 0.3% * 20%
+const PROTOCOL_FEE: u128 = 6_000_000;
+
+fn treasury_lock(scripts: &PoolScripts) -> Script {
+    scripts
+        .user_lock
+        .clone()
+        .as_builder()
+        .args(Bytes::from(vec![3]).pack())
+        .build()
+}
+
+fn treasury_lock_hash(scripts: &PoolScripts) -> [u8; 32] {
+    type_hash(&treasury_lock(scripts))
+}
+
+fn fee_pool_data(
+    scripts: &PoolScripts,
+    ckb_reserve: u128,
+    sudt_reserve: u128,
+    protocol_fees: (u128, u128),
+    protocol_fee_share: u16,
+) -> Bytes {
+    PoolState {
+        protocol_fees,
+        protocol_fee_share,
+        treasury_lock_hash: treasury_lock_hash(scripts),
+        ..PoolState::new(ckb_reserve, sudt_reserve, LP_SUPPLY)
+    }
+    .data()
+}
+
+// Spends the pool of CKB_RESERVE and SUDT_RESERVE with input_fees, and a treasury cell if
+// treasury_spent, the new reserves and protocol fees are given by the caller
+fn build_protocol_fee_context(
+    input_fees: (u128, u128),
+    output_reserves: (u128, u128),
+    output_fees: (u128, u128),
+    output_protocol_fee_share: u16,
+    treasury_spent: bool,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+
+    let input_data = fee_pool_data(
+        &scripts,
+        CKB_RESERVE,
+        SUDT_RESERVE,
+        input_fees,
+        PROTOCOL_FEE_SHARE,
+    );
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, CKB_RESERVE + input_fees.0, &input_data),
+        input_data,
+    );
+    let reserve_out_point = context.create_cell(
+        reserve_cell(&scripts),
+        sudt_data(SUDT_RESERVE + input_fees.1),
+    );
+    let mut inputs = vec![pool_out_point, reserve_out_point];
+    if treasury_spent {
+        let treasury_cell = CellOutput::new_builder()
+            .capacity(20_000_000_000u64.pack())
+            .lock(treasury_lock(&scripts))
+            .build();
+        inputs.push(context.create_cell(treasury_cell, Bytes::new()));
+    }
+
+    let (ckb_reserve, sudt_reserve) = output_reserves;
+    let output_data = fee_pool_data(
+        &scripts,
+        ckb_reserve,
+        sudt_reserve,
+        output_fees,
+        output_protocol_fee_share,
+    );
+    let outputs = vec![
+        pool_cell(&scripts, ckb_reserve + output_fees.0, &output_data),
+        reserve_cell(&scripts),
+    ];
+    let outputs_data = vec![output_data, sudt_data(sudt_reserve + output_fees.1)];
+    let tx = build_tx(&mut context, &scripts, inputs, outputs, outputs_data);
+    (context, tx)
+}
+
+// AMOUNT_IN CKB is swapped for MAX_AMOUNT_OUT sudt, and the protocol keeps ckb_protocol_fee of it
+fn build_protocol_fee_swap_context(
+    ckb_protocol_fee: u128,
+    output_protocol_fee_share: u16,
+) -> (Context, TransactionView) {
+    build_protocol_fee_context(
+        (0, 0),
+        (
+            CKB_RESERVE + AMOUNT_IN - ckb_protocol_fee,
+            SUDT_RESERVE - MAX_AMOUNT_OUT,
+        ),
+        (ckb_protocol_fee, 0),
+        output_protocol_fee_share,
+        false,
+    )
+}
+
+#[test]
+fn test_swap_with_protocol_fee() {
+    let (mut context, tx) = build_protocol_fee_swap_context(PROTOCOL_FEE, PROTOCOL_FEE_SHARE);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_swap_with_wrong_protocol_fee() {
+    let (mut context, tx) =
+        build_protocol_fee_swap_context(PROTOCOL_FEE - 1, PROTOCOL_FEE_SHARE);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(58).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_switching_protocol_fee_off() {
+    let (mut context, tx) = build_protocol_fee_swap_context(0, 0);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(59).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_withdraw_protocol_fee() {
+    let (mut context, tx) = build_protocol_fee_context(
+        (PROTOCOL_FEE, PROTOCOL_FEE),
+        (CKB_RESERVE, SUDT_RESERVE),
+        (0, 0),
+        0,
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_withdraw_protocol_fee_without_treasury() {
+    let (mut context, tx) = build_protocol_fee_context(
+        (PROTOCOL_FEE, PROTOCOL_FEE),
+        (CKB_RESERVE, SUDT_RESERVE),
+        (0, 0),
+        PROTOCOL_FEE_SHARE,
+        false,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(58).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_withdraw_reserves_as_treasury() {
+    let (mut context, tx) = build_protocol_fee_context(
+        (PROTOCOL_FEE, PROTOCOL_FEE),
+        (CKB_RESERVE - AMOUNT_IN, SUDT_RESERVE),
+        (PROTOCOL_FEE, PROTOCOL_FEE),
+        PROTOCOL_FEE_SHARE,
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(58).input_type_script(script_cell_index)
+    );
+}
+
+const AMPLIFICATION: u64 = 100;
+// 100 CKB is swapped in, and 99.69505444 sudt can be taken out at most from the StableSwap pool
+const MAX_STABLE_AMOUNT_OUT: u128 = 9_969_505_444;
+// The CKB reserve is doubled, which takes 9509.5246626 sudt out at most
+const MAX_STABLE_IMBALANCED_AMOUNT_OUT: u128 = 950_952_466_260;
+
+fn stable_pool_data(ckb_reserve: u128, sudt_reserve: u128, amplification: u64) -> Bytes {
+    PoolState {
+        amplification,
+        ..PoolState::new(ckb_reserve, sudt_reserve, LP_SUPPLY)
+    }
+    .data()
+}
+
+// Swaps amount_in CKB for amount_out sudt in the StableSwap pool of CKB_RESERVE and SUDT_RESERVE
+fn build_stable_swap_context(
+    amount_in: u128,
+    amount_out: u128,
+    output_amplification: u64,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+
+    let input_data = stable_pool_data(CKB_RESERVE, SUDT_RESERVE, AMPLIFICATION);
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, CKB_RESERVE, &input_data),
+        input_data,
+    );
+    let reserve_out_point = context.create_cell(reserve_cell(&scripts), sudt_data(SUDT_RESERVE));
+
+    let ckb_reserve = CKB_RESERVE + amount_in;
+    let sudt_reserve = SUDT_RESERVE - amount_out;
+    let output_data = stable_pool_data(ckb_reserve, sudt_reserve, output_amplification);
+    let outputs = vec![
+        pool_cell(&scripts, ckb_reserve, &output_data),
+        reserve_cell(&scripts),
+    ];
+    let outputs_data = vec![output_data, sudt_data(sudt_reserve)];
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![pool_out_point, reserve_out_point],
+        outputs,
+        outputs_data,
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_stable_swap() {
+    let (mut context, tx) =
+        build_stable_swap_context(AMOUNT_IN, MAX_STABLE_AMOUNT_OUT, AMPLIFICATION);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_stable_swap_too_much() {
+    let (mut context, tx) =
+        build_stable_swap_context(AMOUNT_IN, MAX_STABLE_AMOUNT_OUT + 1, AMPLIFICATION);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(52).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_stable_swap_imbalanced_pool() {
+    // Newton's method takes more rounds away from the balance point
+    let (mut context, tx) = build_stable_swap_context(
+        CKB_RESERVE,
+        MAX_STABLE_IMBALANCED_AMOUNT_OUT,
+        AMPLIFICATION,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_stable_swap_imbalanced_pool_too_much() {
+    let (mut context, tx) = build_stable_swap_context(
+        CKB_RESERVE,
+        MAX_STABLE_IMBALANCED_AMOUNT_OUT + 1,
+        AMPLIFICATION,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(52).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_stable_swap_changing_amplification() {
+    let (mut context, tx) =
+        build_stable_swap_context(AMOUNT_IN, MAX_AMOUNT_OUT, AMPLIFICATION * 2);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(47).input_type_script(script_cell_index)
+    );
+}
+
+// 10000 of a base sudt of 6 decimals against 10000 sudt of 8 decimals, whose multipliers bring
+// both to 8 decimals, so 100 of the base sudt is swapped for MAX_STABLE_AMOUNT_OUT sudt at most
+const BASE_RESERVE: u128 = 10_000_000_000;
+const BASE_AMOUNT_IN: u128 = 100_000_000;
+const SUDT_PAIR_MULTIPLIERS: (u64, u64) = (100, 1);
+
+fn sudt_pair_pool_data(base_reserve: u128, sudt_reserve: u128, multipliers: (u64, u64)) -> Bytes {
+    PoolState {
+        amplification: AMPLIFICATION,
+        multipliers,
+        ..PoolState::new(base_reserve, sudt_reserve, LP_SUPPLY)
+    }
+    .data()
+}
+
+// Swaps BASE_AMOUNT_IN base sudt for amount_out sudt in the StableSwap sUDT/sUDT pool of
+// BASE_RESERVE and SUDT_RESERVE
+fn build_sudt_pair_swap_context(
+    amount_out: u128,
+    input_multipliers: (u64, u64),
+    output_multipliers: (u64, u64),
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    set_sudt_pair(&mut scripts);
+
+    let input_data = sudt_pair_pool_data(BASE_RESERVE, SUDT_RESERVE, input_multipliers);
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, BASE_RESERVE, &input_data),
+        input_data,
+    );
+    let reserve_out_point = context.create_cell(reserve_cell(&scripts), sudt_data(SUDT_RESERVE));
+    let base_reserve_out_point =
+        context.create_cell(base_reserve_cell(&scripts), sudt_data(BASE_RESERVE));
+
+    let base_reserve = BASE_RESERVE + BASE_AMOUNT_IN;
+    let sudt_reserve = SUDT_RESERVE - amount_out;
+    let output_data = sudt_pair_pool_data(base_reserve, sudt_reserve, output_multipliers);
+    let outputs = vec![
+        pool_cell(&scripts, base_reserve, &output_data),
+        reserve_cell(&scripts),
+        base_reserve_cell(&scripts),
+    ];
+    let outputs_data = vec![
+        output_data,
+        sudt_data(sudt_reserve),
+        sudt_data(base_reserve),
+    ];
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![pool_out_point, reserve_out_point, base_reserve_out_point],
+        outputs,
+        outputs_data,
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_stable_swap_sudt_pair() {
+    let (mut context, tx) = build_sudt_pair_swap_context(
+        MAX_STABLE_AMOUNT_OUT,
+        SUDT_PAIR_MULTIPLIERS,
+        SUDT_PAIR_MULTIPLIERS,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_stable_swap_sudt_pair_too_much() {
+    let (mut context, tx) = build_sudt_pair_swap_context(
+        MAX_STABLE_AMOUNT_OUT + 1,
+        SUDT_PAIR_MULTIPLIERS,
+        SUDT_PAIR_MULTIPLIERS,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(52).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_stable_swap_sudt_pair_without_multipliers() {
+    // the pool is far off the balance point without the scaling, which takes 6.08280438 sudt out
+    // at most
+    let (mut context, tx) = build_sudt_pair_swap_context(MAX_STABLE_AMOUNT_OUT, (1, 1), (1, 1));
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(52).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_stable_swap_sudt_pair_changing_multipliers() {
+    let (mut context, tx) =
+        build_sudt_pair_swap_context(MAX_STABLE_AMOUNT_OUT, SUDT_PAIR_MULTIPLIERS, (1, 1));
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(47).input_type_script(script_cell_index)
+    );
+}
+
+// The price of either asset is 1 in the pool of CKB_RESERVE and SUDT_RESERVE, times PRICE_PARAM
+const PRICE: u128 = 10_000_000_000;
+const PRICE_CUMULATIVES: (u128, u128) = (1_000_000_000_000_000_000, 2_000_000_000_000_000_000);
+// The pool is swapped again a minute later
+const ELAPSED: u64 = 60_000;
+const SINCE_ABSOLUTE_TIMESTAMP: u64 = 0x4000_0000_0000_0000;
+const SINCE_RELATIVE_TIMESTAMP: u64 = 0xc000_0000_0000_0000;
+
+// The relative since of a pool input committed ELAPSED before the transaction
+fn pool_age_since() -> u64 {
+    SINCE_RELATIVE_TIMESTAMP | ELAPSED / 1000
+}
+
+// Spends the pool of input_state into the one of output_state, where the current time is given by
+// the header dep at header_timestamp and the since of the pool input
+fn build_pool_state_context(
+    header_timestamp: Option<u64>,
+    since: u64,
+    input_state: PoolState,
+    output_state: PoolState,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+
+    let input_data = input_state.data();
+    let pool_out_point = context.create_cell(
+        pool_cell(&scripts, input_state.ckb_reserve, &input_data),
+        input_data,
+    );
+    let reserve_out_point = context.create_cell(
+        reserve_cell(&scripts),
+        sudt_data(input_state.sudt_reserve),
+    );
+
+    let output_data = output_state.data();
+    let mut builder = TransactionBuilder::default()
+        .input(
+            CellInput::new_builder()
+                .previous_output(pool_out_point)
+                .since(since.pack())
+                .build(),
+        )
+        .input(
+            CellInput::new_builder()
+                .previous_output(reserve_out_point)
+                .build(),
+        )
+        .output(pool_cell(&scripts, output_state.ckb_reserve, &output_data))
+        .output(reserve_cell(&scripts))
+        .outputs_data(vec![output_data, sudt_data(output_state.sudt_reserve)].pack())
+        .cell_deps(scripts.cell_deps.clone());
+    if let Some(timestamp) = header_timestamp {
+        builder = builder.header_dep(timestamp_header_dep(&mut context, timestamp));
+    }
+    (context, builder.build())
+}
+
+// Swaps AMOUNT_IN CKB for MAX_AMOUNT_OUT sudt in the pool of PRICE_CUMULATIVES, which is stamped
+// at output_timestamp
+fn build_twap_context(
+    header_timestamp: Option<u64>,
+    since: u64,
+    output_timestamp: u64,
+    output_price_cumulatives: (u128, u128),
+) -> (Context, TransactionView) {
+    let input_state = PoolState {
+        price_cumulatives: PRICE_CUMULATIVES,
+        ..PoolState::new(CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY)
+    };
+    let output_state = PoolState {
+        price_cumulatives: output_price_cumulatives,
+        last_timestamp: output_timestamp,
+        ..PoolState::new(
+            CKB_RESERVE + AMOUNT_IN,
+            SUDT_RESERVE - MAX_AMOUNT_OUT,
+            LP_SUPPLY,
+        )
+    };
+    build_pool_state_context(header_timestamp, since, input_state, output_state)
+}
+
+fn updated_price_cumulatives() -> (u128, u128) {
+    let delta = PRICE * ELAPSED as u128;
+    (PRICE_CUMULATIVES.0 + delta, PRICE_CUMULATIVES.1 + delta)
+}
+
+#[test]
+fn test_swap_updating_price_accumulators() {
+    let (mut context, tx) = build_twap_context(
+        Some(POOL_TIMESTAMP + ELAPSED),
+        pool_age_since(),
+        POOL_TIMESTAMP + ELAPSED,
+        updated_price_cumulatives(),
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_swap_stamped_by_absolute_since() {
+    // the absolute since gives the current time, but it doesn't prove the age of the pool, so
+    // no price is credited
+    let since = SINCE_ABSOLUTE_TIMESTAMP | (POOL_TIMESTAMP + ELAPSED) / 1000;
+    let (mut context, tx) =
+        build_twap_context(None, since, POOL_TIMESTAMP + ELAPSED, PRICE_CUMULATIVES);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_swap_crediting_more_than_pool_age() {
+    // the pool input is only proven half a minute old
+    let since = SINCE_RELATIVE_TIMESTAMP | ELAPSED / 2000;
+    let (mut context, tx) = build_twap_context(
+        Some(POOL_TIMESTAMP + ELAPSED),
+        since,
+        POOL_TIMESTAMP + ELAPSED,
+        updated_price_cumulatives(),
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(61).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_crediting_price_of_same_block() {
+    // the first swap moves the price with a header dep of the last update, so no time passes
+    let swapped_state = || PoolState {
+        price_cumulatives: PRICE_CUMULATIVES,
+        ..PoolState::new(
+            CKB_RESERVE + AMOUNT_IN,
+            SUDT_RESERVE - MAX_AMOUNT_OUT,
+            LP_SUPPLY,
+        )
+    };
+    let (mut context, tx) =
+        build_twap_context(Some(POOL_TIMESTAMP), 0, POOL_TIMESTAMP, PRICE_CUMULATIVES);
+    let tx = context.complete_tx(tx);
+    context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+
+    // the second transaction of the block spends the new pool with a fresh header dep, and credits
+    // the moved price over a minute
+    let sudt_price = (CKB_RESERVE + AMOUNT_IN) * PRICE / (SUDT_RESERVE - MAX_AMOUNT_OUT);
+    let ckb_price = (SUDT_RESERVE - MAX_AMOUNT_OUT) * PRICE / (CKB_RESERVE + AMOUNT_IN);
+    let output_state = PoolState {
+        price_cumulatives: (
+            PRICE_CUMULATIVES.0 + sudt_price * ELAPSED as u128,
+            PRICE_CUMULATIVES.1 + ckb_price * ELAPSED as u128,
+        ),
+        last_timestamp: POOL_TIMESTAMP + ELAPSED,
+        ..swapped_state()
+    };
+    let (mut context, tx) = build_pool_state_context(
+        Some(POOL_TIMESTAMP + ELAPSED),
+        0,
+        swapped_state(),
+        output_state,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(61).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_with_wrong_price_accumulators() {
+    let (sudt_price_cumulative, ckb_price_cumulative) = updated_price_cumulatives();
+    let (mut context, tx) = build_twap_context(
+        Some(POOL_TIMESTAMP + ELAPSED),
+        pool_age_since(),
+        POOL_TIMESTAMP + ELAPSED,
+        (sudt_price_cumulative, ckb_price_cumulative + 1),
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(61).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_with_stale_header() {
+    // the pool is stamped at the header dep, which is before its last update
+    let (mut context, tx) = build_twap_context(
+        Some(POOL_TIMESTAMP - ELAPSED),
+        pool_age_since(),
+        POOL_TIMESTAMP - ELAPSED,
+        PRICE_CUMULATIVES,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(61).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_swap_without_timestamp() {
+    let (mut context, tx) = build_twap_context(
+        None,
+        pool_age_since(),
+        POOL_TIMESTAMP + ELAPSED,
+        updated_price_cumulatives(),
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(60).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_with_wrong_pool_id() {
+    let (mut context, tx) = build_registered_creation_context(
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        1,
+        Bytes::new(),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(62).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_without_registration() {
+    let (mut context, tx) = build_registered_creation_context(
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::new(),
+        false,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(63).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_for_registered_pair() {
+    // another pool of the pair is registered already
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+    let mut registry_entries = pair_hash(&scripts).to_vec();
+    registry_entries.extend_from_slice(scripts.pool_type.calc_script_hash().as_slice());
+
+    let (mut context, tx) = build_registered_creation_context(
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::from(registry_entries),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 3;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(64).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_in_foreign_registry() {
+    // an anyone can update registry with the pool entry
+    let (mut context, tx) = build_custom_creation_context(
+        |scripts| {
+            let registry_type = scripts
+                .sudt_type
+                .clone()
+                .as_builder()
+                .args(Bytes::from(vec![7u8; 32]).pack())
+                .build();
+            set_registry(scripts, registry_type);
+            registry_header(scripts)
+        },
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::new(),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(63).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_register_foreign_pool() {
+    // a cell of another type script with the pool args takes the pair in the registry
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+    let foreign_type = scripts
+        .sudt_type
+        .clone()
+        .as_builder()
+        .args(scripts.pool_type.args())
+        .build();
+    let user_out_point =
+        context.create_cell(user_cell(&scripts, 200_000_000_000, false), Bytes::new());
+    let registry_header = registry_header(&scripts);
+    let registry_out_point =
+        context.create_cell(registry_cell(&scripts), Bytes::from(registry_header.clone()));
+
+    let mut registry_data = registry_header;
+    registry_data.extend_from_slice(&pair_hash(&scripts));
+    registry_data.extend_from_slice(foreign_type.calc_script_hash().as_slice());
+    let foreign_cell = user_cell(&scripts, 100_000_000_000, false)
+        .as_builder()
+        .type_(Some(foreign_type).pack())
+        .build();
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![user_out_point, registry_out_point],
+        vec![foreign_cell, registry_cell(&scripts)],
+        vec![Bytes::new(), Bytes::from(registry_data)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(65).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_of_other_lp_script() {
+    // the registry pins another LP token script than the one of the pool
+    let (mut context, tx) = build_custom_creation_context(
+        |scripts| {
+            let mut header = registry_header(scripts);
+            header[..33].copy_from_slice(&[9u8; 33]);
+            header
+        },
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::new(),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 3;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(65).input_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_with_protocol_fee_share() {
+    let (mut context, tx) = build_creation_context(LP_SUPPLY, LP_SUPPLY - MINIMUM_LIQUIDITY);
+    // the creator switches on the protocol fee in the protocol_fee_share of the pool data
+    let mut outputs_data: Vec<Bytes> = tx
+        .outputs_data()
+        .into_iter()
+        .map(|data| data.unpack())
+        .collect();
+    let mut data = outputs_data[0].to_vec();
+    data[80..82].copy_from_slice(&PROTOCOL_FEE_SHARE.to_le_bytes());
+    outputs_data[0] = Bytes::from(data);
+    let tx = tx
+        .as_advanced_builder()
+        .set_outputs_data(outputs_data.into_iter().map(|data| data.pack()).collect())
+        .build();
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(58).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_pool_of_other_treasury() {
+    // the registry pins another treasury than the one of the pool
+    let (mut context, tx) = build_custom_creation_context(
+        |scripts| {
+            let mut header = registry_header(scripts);
+            header[33..].copy_from_slice(&[9u8; 32]);
+            header
+        },
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::new(),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(59).output_type_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_create_sudt_pair_pool() {
+    // the base sudt takes the place of CKB in the pool id, the registry and the reserves
+    let (mut context, tx) = build_custom_creation_context(
+        |scripts| {
+            set_sudt_pair(scripts);
+            registry_header(scripts)
+        },
+        LP_SUPPLY,
+        LP_SUPPLY - MINIMUM_LIQUIDITY,
+        0,
+        Bytes::new(),
+        true,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+// The registry id is derived from the first input and the output index 0, and it starts with
+// the header and the registry entries
+fn build_registry_creation_context(registry_entries: Bytes) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let scripts = deploy_pool_scripts(&mut context);
+    let user_out_point =
+        context.create_cell(user_cell(&scripts, 200_000_000_000, false), Bytes::new());
+
+    let registry_id = blake2b_256(&[user_out_point.as_slice(), &0u64.to_le_bytes()]);
+    let registry_type = scripts
+        .registry_type
+        .clone()
+        .as_builder()
+        .args(Bytes::from(registry_id.to_vec()).pack())
+        .build();
+    let mut registry_data = registry_header(&scripts);
+    registry_data.extend_from_slice(&registry_entries);
+    let output = registry_cell(&scripts)
+        .as_builder()
+        .type_(Some(registry_type).pack())
+        .build();
+    let tx = build_tx(
+        &mut context,
+        &scripts,
+        vec![user_out_point],
+        vec![output],
+        vec![Bytes::from(registry_data)],
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_create_registry() {
+    let (mut context, tx) = build_registry_creation_context(Bytes::new());
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_create_registry_with_entries() {
+    let (mut context, tx) = build_registry_creation_context(Bytes::from(vec![0u8; 64]));
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
     let script_cell_index = 0;
     assert_error_eq!(
         err,
-        ScriptError::ValidationFailure(ERROR_EMPTY_ARGS).input_lock_script(script_cell_index)
+        ScriptError::ValidationFailure(65).output_type_script(script_cell_index)
     );
 }
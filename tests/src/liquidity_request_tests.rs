@@ -0,0 +1,646 @@
+use super::liquidity_poll_tests::{
+    base_reserve_cell, deploy_pool_scripts, pool_cell, pool_data, reserve_cell, set_sudt_pair,
+    sudt_data, timestamp_header_dep, PoolScripts, AMOUNT_IN, CKB_RESERVE, LP_SUPPLY,
+    MAX_AMOUNT_OUT, MAX_CYCLES, POOL_TIMESTAMP, SUDT_RESERVE,
+};
+use super::order_book_tests::{blake160, sign_tx};
+use super::*;
+use ckb_system_scripts::BUNDLED_CELL;
+use ckb_testtool::context::Context;
+use ckb_tool::ckb_crypto::secp::{Generator, Privkey};
+use ckb_tool::ckb_error::assert_error_eq;
+use ckb_tool::ckb_script::ScriptError;
+use ckb_tool::ckb_types::{
+    bytes::Bytes,
+    core::{TransactionBuilder, TransactionView},
+    packed::*,
+    prelude::*,
+};
+use std::fs;
+
+const SWAP_TO_SUDT: u8 = 0;
+const SWAP_TO_CKB: u8 = 1;
+const ADD_LIQUIDITY: u8 = 2;
+const REMOVE_LIQUIDITY: u8 = 3;
+
+// The capacity of the cells paid to the user
+const USER_CAPACITY: u64 = 20_000_000_000;
+// The request cell pays AMOUNT_IN CKB to the pool besides the capacity of the user cell
+const REQUEST_CAPACITY: u64 = USER_CAPACITY + AMOUNT_IN as u64;
+
+fn request_data(
+    sudt_amount: u128,
+    request_type: u8,
+    user_lock_hash: Byte32,
+    min_amount_out: u128,
+    min_capacity_out: u64,
+) -> Bytes {
+    let mut data = Vec::new();
+    data.extend_from_slice(&sudt_amount.to_le_bytes());
+    data.push(request_type);
+    data.extend_from_slice(user_lock_hash.as_slice());
+    data.extend_from_slice(&min_amount_out.to_le_bytes());
+    data.extend_from_slice(&min_capacity_out.to_le_bytes());
+    Bytes::from(data)
+}
+
+// The request lock of the owner pubkey_hash against the pool of the scripts
+fn deploy_request_lock(
+    context: &mut Context,
+    scripts: &mut PoolScripts,
+    pubkey_hash: &[u8],
+) -> Script {
+    let request_bin: Bytes = Loader::default().load_binary("liquidity-request-contract");
+    let request_out_point = context.deploy_cell(request_bin);
+    scripts
+        .cell_deps
+        .push(CellDep::new_builder().out_point(request_out_point.clone()).build());
+
+    let mut args = pubkey_hash.to_vec();
+    args.extend_from_slice(scripts.pool_type.calc_script_hash().as_slice());
+    context
+        .build_script(&request_out_point, Bytes::from(args))
+        .expect("script")
+}
+
+fn request_cell(request_lock: &Script, type_: Option<Script>) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(REQUEST_CAPACITY.pack())
+        .lock(request_lock.clone())
+        .type_(type_.pack())
+        .build()
+}
+
+fn user_output(lock: &Script, capacity: u64, type_: Option<Script>) -> CellOutput {
+    CellOutput::new_builder()
+        .capacity(capacity.pack())
+        .lock(lock.clone())
+        .type_(type_.pack())
+        .build()
+}
+
+// The aggregator spends the pool of CKB_RESERVE, SUDT_RESERVE and LP_SUPPLY with the request
+// at input 2, and pays the user with output 2. The base sudt reserve cell of a sUDT/sUDT pool
+// holds CKB_RESERVE, which moves the request to input 3 and the user to output 3.
+fn build_execution_context(
+    context: &mut Context,
+    scripts: &PoolScripts,
+    request: (CellOutput, Bytes),
+    output_pool: (u128, u128, u128),
+    user_output: (CellOutput, Bytes),
+) -> TransactionView {
+    let input_pool_data = pool_data(CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY);
+    let pool_out_point = context.create_cell(
+        pool_cell(scripts, CKB_RESERVE, &input_pool_data),
+        input_pool_data,
+    );
+    let reserve_out_point = context.create_cell(reserve_cell(scripts), sudt_data(SUDT_RESERVE));
+    let mut inputs = vec![pool_out_point, reserve_out_point];
+
+    let (ckb_reserve, sudt_reserve, lp_supply) = output_pool;
+    let output_pool_data = pool_data(ckb_reserve, sudt_reserve, lp_supply);
+    let mut outputs = vec![
+        pool_cell(scripts, ckb_reserve, &output_pool_data),
+        reserve_cell(scripts),
+    ];
+    let mut outputs_data = vec![output_pool_data, sudt_data(sudt_reserve)];
+    if scripts.base_type.is_some() {
+        inputs.push(context.create_cell(base_reserve_cell(scripts), sudt_data(CKB_RESERVE)));
+        outputs.push(base_reserve_cell(scripts));
+        outputs_data.push(sudt_data(ckb_reserve));
+    }
+    inputs.push(context.create_cell(request.0, request.1));
+    outputs.push(user_output.0);
+    outputs_data.push(user_output.1);
+
+    let tx = TransactionBuilder::default()
+        .inputs(inputs.into_iter().map(|out_point| {
+            CellInput::new_builder()
+                .previous_output(out_point)
+                .build()
+        }))
+        .outputs(outputs)
+        .outputs_data(outputs_data.pack())
+        .cell_deps(scripts.cell_deps.clone())
+        .header_dep(timestamp_header_dep(context, POOL_TIMESTAMP))
+        .build();
+    context.complete_tx(tx)
+}
+
+// A request to swap AMOUNT_IN CKB for min_amount_out sudt at least, which gets amount_out sudt
+// paid to the user, or to another lock if to_user is false
+fn build_swap_request_context(
+    min_amount_out: u128,
+    amount_out: u128,
+    to_user: bool,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let data = request_data(
+        0,
+        SWAP_TO_SUDT,
+        scripts.user_lock.calc_script_hash(),
+        min_amount_out,
+        USER_CAPACITY,
+    );
+    let user_lock = if to_user {
+        scripts.user_lock.clone()
+    } else {
+        scripts
+            .user_lock
+            .clone()
+            .as_builder()
+            .args(Bytes::from(vec![2]).pack())
+            .build()
+    };
+    let output = user_output(&user_lock, USER_CAPACITY, Some(scripts.sudt_type.clone()));
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, None), data),
+        (CKB_RESERVE + AMOUNT_IN, SUDT_RESERVE - amount_out, LP_SUPPLY),
+        (output, sudt_data(amount_out)),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_execute_swap_request() {
+    let (mut context, tx) = build_swap_request_context(MAX_AMOUNT_OUT, MAX_AMOUNT_OUT, true);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_swap_request_below_minimum() {
+    let (mut context, tx) =
+        build_swap_request_context(MAX_AMOUNT_OUT, MAX_AMOUNT_OUT - 1, true);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_execute_swap_request_to_other_lock() {
+    let (mut context, tx) = build_swap_request_context(MAX_AMOUNT_OUT, MAX_AMOUNT_OUT, false);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// The pool can't give MAX_AMOUNT_OUT + 1, so the request cell, which holds AMOUNT_IN of the asset
+// of asset_type if any, is given back with capacity
+fn build_refund_context(
+    request_type: u8,
+    asset_type: fn(&PoolScripts) -> Option<Script>,
+    capacity: u64,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let asset_type = asset_type(&scripts);
+    let (asset_amount, output_data) = match asset_type {
+        Some(_) => (AMOUNT_IN, sudt_data(AMOUNT_IN)),
+        None => (0, Bytes::new()),
+    };
+    let data = request_data(
+        asset_amount,
+        request_type,
+        scripts.user_lock.calc_script_hash(),
+        MAX_AMOUNT_OUT + 1,
+        USER_CAPACITY,
+    );
+    let output = user_output(&scripts.user_lock, capacity, asset_type.clone());
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, asset_type), data),
+        (CKB_RESERVE, SUDT_RESERVE, LP_SUPPLY),
+        (output, output_data),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_refund_swap_request() {
+    let (mut context, tx) = build_refund_context(SWAP_TO_SUDT, |_| None, REQUEST_CAPACITY);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_refund_swap_request_partially() {
+    let (mut context, tx) = build_refund_context(SWAP_TO_SUDT, |_| None, REQUEST_CAPACITY - 1);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// A request to swap AMOUNT_IN sudt for min_ckb_out CKB at least, which gets ckb_out CKB paid to
+// the user besides the capacity of the request cell
+fn build_swap_to_ckb_request_context(
+    min_ckb_out: u128,
+    ckb_out: u128,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let data = request_data(
+        AMOUNT_IN,
+        SWAP_TO_CKB,
+        scripts.user_lock.calc_script_hash(),
+        0,
+        REQUEST_CAPACITY + min_ckb_out as u64,
+    );
+    let output = user_output(&scripts.user_lock, REQUEST_CAPACITY + ckb_out as u64, None);
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, Some(scripts.sudt_type.clone())), data),
+        (CKB_RESERVE - ckb_out, SUDT_RESERVE + AMOUNT_IN, LP_SUPPLY),
+        (output, Bytes::new()),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_execute_swap_to_ckb_request() {
+    let (mut context, tx) = build_swap_to_ckb_request_context(MAX_AMOUNT_OUT, MAX_AMOUNT_OUT);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_swap_to_ckb_request_below_minimum() {
+    let (mut context, tx) =
+        build_swap_to_ckb_request_context(MAX_AMOUNT_OUT, MAX_AMOUNT_OUT - 1);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_refund_swap_to_ckb_request() {
+    let (mut context, tx) = build_refund_context(
+        SWAP_TO_CKB,
+        |scripts| Some(scripts.sudt_type.clone()),
+        REQUEST_CAPACITY,
+    );
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_refund_swap_to_ckb_request_partially() {
+    let (mut context, tx) = build_refund_context(
+        SWAP_TO_CKB,
+        |scripts| Some(scripts.sudt_type.clone()),
+        REQUEST_CAPACITY - 1,
+    );
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// A request to swap AMOUNT_IN of one sudt of a sUDT/sUDT pool for min_amount_out of the other at
+// least, which gets amount_out paid to the user. The base sudt takes the place of CKB.
+fn build_sudt_pair_swap_request_context(
+    request_type: u8,
+    min_amount_out: u128,
+    amount_out: u128,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    set_sudt_pair(&mut scripts);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let base_type = scripts.base_type.clone();
+    let sudt_type = Some(scripts.sudt_type.clone());
+    let (type_in, type_out, output_pool) = if request_type == SWAP_TO_SUDT {
+        let output_pool = (CKB_RESERVE + AMOUNT_IN, SUDT_RESERVE - amount_out, LP_SUPPLY);
+        (base_type, sudt_type, output_pool)
+    } else {
+        let output_pool = (CKB_RESERVE - amount_out, SUDT_RESERVE + AMOUNT_IN, LP_SUPPLY);
+        (sudt_type, base_type, output_pool)
+    };
+    let data = request_data(
+        AMOUNT_IN,
+        request_type,
+        scripts.user_lock.calc_script_hash(),
+        min_amount_out,
+        USER_CAPACITY,
+    );
+    let output = user_output(&scripts.user_lock, USER_CAPACITY, type_out);
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, type_in), data),
+        output_pool,
+        (output, sudt_data(amount_out)),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_execute_sudt_pair_swap_request() {
+    let (mut context, tx) =
+        build_sudt_pair_swap_request_context(SWAP_TO_SUDT, MAX_AMOUNT_OUT, MAX_AMOUNT_OUT);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_sudt_pair_swap_to_base_request() {
+    let (mut context, tx) =
+        build_sudt_pair_swap_request_context(SWAP_TO_CKB, MAX_AMOUNT_OUT, MAX_AMOUNT_OUT);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_sudt_pair_swap_to_base_request_below_minimum() {
+    let (mut context, tx) =
+        build_sudt_pair_swap_request_context(SWAP_TO_CKB, MAX_AMOUNT_OUT, MAX_AMOUNT_OUT - 1);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 3;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// A request to deposit AMOUNT_IN CKB and AMOUNT_IN sudt for min_lp_out LP tokens at least
+fn build_add_liquidity_request_context(min_lp_out: u128) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let data = request_data(
+        AMOUNT_IN,
+        ADD_LIQUIDITY,
+        scripts.user_lock.calc_script_hash(),
+        min_lp_out,
+        USER_CAPACITY,
+    );
+    let lp_minted = LP_SUPPLY / 100;
+    let output = user_output(
+        &scripts.user_lock,
+        USER_CAPACITY,
+        Some(scripts.lp_type.clone()),
+    );
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, Some(scripts.sudt_type.clone())), data),
+        (CKB_RESERVE + AMOUNT_IN, SUDT_RESERVE + AMOUNT_IN, LP_SUPPLY + lp_minted),
+        (output, sudt_data(lp_minted)),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_execute_add_liquidity_request() {
+    let (mut context, tx) = build_add_liquidity_request_context(LP_SUPPLY / 100);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_add_liquidity_request_below_minimum() {
+    let (mut context, tx) = build_add_liquidity_request_context(LP_SUPPLY / 100 + 1);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// A request to burn 1% of the LP supply for min_sudt_out sudt and min_ckb_out CKB at least, and
+// the aggregator pays the whole share, 1% of either reserve, to the user
+fn build_remove_liquidity_request_context(
+    min_sudt_out: u128,
+    min_ckb_out: u128,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &[0u8; 20]);
+
+    let lp_burned = LP_SUPPLY / 100;
+    let ckb_out = CKB_RESERVE / 100;
+    let sudt_out = SUDT_RESERVE / 100;
+    let data = request_data(
+        lp_burned,
+        REMOVE_LIQUIDITY,
+        scripts.user_lock.calc_script_hash(),
+        min_sudt_out,
+        REQUEST_CAPACITY + min_ckb_out as u64,
+    );
+    let output = user_output(
+        &scripts.user_lock,
+        REQUEST_CAPACITY + ckb_out as u64,
+        Some(scripts.sudt_type.clone()),
+    );
+    let tx = build_execution_context(
+        &mut context,
+        &scripts,
+        (request_cell(&request_lock, Some(scripts.lp_type.clone())), data),
+        (CKB_RESERVE - ckb_out, SUDT_RESERVE - sudt_out, LP_SUPPLY - lp_burned),
+        (output, sudt_data(sudt_out)),
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_execute_remove_liquidity_request() {
+    let (mut context, tx) =
+        build_remove_liquidity_request_context(SUDT_RESERVE / 100, CKB_RESERVE / 100);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_execute_remove_liquidity_request_below_sudt_minimum() {
+    let (mut context, tx) =
+        build_remove_liquidity_request_context(SUDT_RESERVE / 100 + 1, CKB_RESERVE / 100);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_execute_remove_liquidity_request_below_ckb_minimum() {
+    let (mut context, tx) =
+        build_remove_liquidity_request_context(SUDT_RESERVE / 100, CKB_RESERVE / 100 + 1);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_refund_remove_liquidity_request() {
+    let (mut context, tx) = build_refund_context(
+        REMOVE_LIQUIDITY,
+        |scripts| Some(scripts.lp_type.clone()),
+        REQUEST_CAPACITY,
+    );
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_refund_remove_liquidity_request_partially() {
+    let (mut context, tx) = build_refund_context(
+        REMOVE_LIQUIDITY,
+        |scripts| Some(scripts.lp_type.clone()),
+        REQUEST_CAPACITY - 1,
+    );
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 2;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(57).input_lock_script(script_cell_index)
+    );
+}
+
+// The owner takes the request back without the pool
+fn build_cancel_context(privkey: &Privkey) -> (Context, TransactionView) {
+    let pubkey = privkey.pubkey().expect("pubkey");
+    let pubkey_hash = blake160(&pubkey.serialize());
+
+    let mut context = Context::default();
+    let mut scripts = deploy_pool_scripts(&mut context);
+    let request_lock = deploy_request_lock(&mut context, &mut scripts, &pubkey_hash);
+
+    let secp256k1_bin: Bytes =
+        fs::read("../ckb-miscellaneous-scripts/build/secp256k1_blake2b_sighash_all_dual")
+            .expect("load secp256k1")
+            .into();
+    let secp256k1_out_point = context.deploy_cell(secp256k1_bin);
+    let secp256k1_data_bin = BUNDLED_CELL.get("specs/cells/secp256k1_data").unwrap();
+    let secp256k1_data_out_point = context.deploy_cell(secp256k1_data_bin.to_vec().into());
+
+    let data = request_data(
+        0,
+        SWAP_TO_SUDT,
+        scripts.user_lock.calc_script_hash(),
+        MAX_AMOUNT_OUT,
+        USER_CAPACITY,
+    );
+    let request_out_point = context.create_cell(request_cell(&request_lock, None), data);
+    let tx = TransactionBuilder::default()
+        .input(
+            CellInput::new_builder()
+                .previous_output(request_out_point)
+                .build(),
+        )
+        .output(user_output(&scripts.user_lock, REQUEST_CAPACITY, None))
+        .output_data(Bytes::new().pack())
+        .cell_deps(scripts.cell_deps)
+        .cell_dep(CellDep::new_builder().out_point(secp256k1_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(secp256k1_data_out_point)
+                .build(),
+        )
+        .build();
+    let tx = context.complete_tx(tx);
+    (context, tx)
+}
+
+#[test]
+fn test_cancel_request() {
+    let privkey = Generator::random_privkey();
+    let (mut context, tx) = build_cancel_context(&privkey);
+    let tx = sign_tx(tx, &privkey);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("consume cycles: {}", cycles);
+}
+
+#[test]
+fn test_cancel_request_with_wrong_key() {
+    let (mut context, tx) = build_cancel_context(&Generator::random_privkey());
+    let tx = sign_tx(tx, &Generator::random_privkey());
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(6).input_lock_script(script_cell_index)
+    );
+}
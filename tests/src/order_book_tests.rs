@@ -1,13 +1,13 @@
 use super::*;
 use ckb_system_scripts::BUNDLED_CELL;
-use ckb_testtool::context::Context;
+use ckb_testtool::{builtin::ALWAYS_SUCCESS, context::Context};
 use ckb_tool::ckb_crypto::secp::{Generator, Privkey};
 use ckb_tool::ckb_error::assert_error_eq;
 use ckb_tool::ckb_hash::{blake2b_256, new_blake2b};
 use ckb_tool::ckb_script::ScriptError;
 use ckb_tool::ckb_types::{
     bytes::Bytes,
-    core::{Capacity, TransactionBuilder, TransactionView},
+    core::{Capacity, HeaderBuilder, TransactionBuilder, TransactionView},
     packed::{self, *},
     prelude::*,
     H256,
@@ -17,14 +17,14 @@ use std::fs;
 
 const MAX_CYCLES: u64 = 1000_0000;
 
-fn blake160(data: &[u8]) -> [u8; 20] {
+pub(crate) fn blake160(data: &[u8]) -> [u8; 20] {
     let mut buf = [0u8; 20];
     let hash = blake2b_256(data);
     buf.clone_from_slice(&hash[..20]);
     buf
 }
 
-fn sign_tx(tx: TransactionView, key: &Privkey) -> TransactionView {
+pub(crate) fn sign_tx(tx: TransactionView, key: &Privkey) -> TransactionView {
     const SIGNATURE_SIZE: usize = 65;
 
     let witnesses_len = tx.witnesses().len();
@@ -33,8 +33,12 @@ fn sign_tx(tx: TransactionView, key: &Privkey) -> TransactionView {
     let mut blake2b = new_blake2b();
     let mut message = [0u8; 32];
     blake2b.update(&tx_hash.raw_data());
-    // digest the first witness
-    let witness = WitnessArgs::default();
+    // digest the first witness, which carries the action of the order
+    let witness = tx
+        .witnesses()
+        .get(0)
+        .map(|witness| WitnessArgs::new_unchecked(witness.raw_data()))
+        .unwrap_or_default();
     let zero_lock: Bytes = {
         let mut buf = Vec::new();
         buf.resize(SIGNATURE_SIZE, 0);
@@ -80,11 +84,42 @@ fn build_test_context(
     outputs_data: Vec<Bytes>,
     input_args: Vec<Bytes>,
     output_args: Vec<Bytes>,
+) -> (Context, TransactionView) {
+    build_udt_test_context(
+        None,
+        inputs_token,
+        outputs_token,
+        inputs_data,
+        outputs_data,
+        input_args,
+        output_args,
+    )
+}
+
+// Like build_test_context, but every order cell is of the udt type deployed from udt_bin if any
+fn build_udt_test_context(
+    udt_bin: Option<Bytes>,
+    inputs_token: Vec<u64>,
+    outputs_token: Vec<u64>,
+    inputs_data: Vec<Bytes>,
+    outputs_data: Vec<Bytes>,
+    input_args: Vec<Bytes>,
+    output_args: Vec<Bytes>,
 ) -> (Context, TransactionView) {
     // deploy dex script
     let mut context = Context::default();
     let dex_bin: Bytes = Loader::default().load_binary("ckb-dex-contract");
     let dex_out_point = context.deploy_cell(dex_bin);
+    let mut cell_deps = vec![CellDep::new_builder().out_point(dex_out_point.clone()).build()];
+
+    // the owner of the udt spends none of the inputs
+    let udt_type = udt_bin.map(|udt_bin| {
+        let udt_out_point = context.deploy_cell(udt_bin);
+        cell_deps.push(CellDep::new_builder().out_point(udt_out_point.clone()).build());
+        context
+            .build_script(&udt_out_point, Bytes::from(vec![0x11; 32]))
+            .expect("script")
+    });
 
     // prepare inputs
     let mut inputs = vec![];
@@ -98,6 +133,7 @@ fn build_test_context(
             CellOutput::new_builder()
                 .capacity(capacity.pack())
                 .lock(dex_script.clone())
+                .type_(udt_type.clone().pack())
                 .build(),
             inputs_data.get(index).unwrap().clone(),
         );
@@ -118,14 +154,14 @@ fn build_test_context(
         let output = CellOutput::new_builder()
             .capacity(capacity.pack())
             .lock(dex_script.clone())
+            .type_(udt_type.clone().pack())
             .build();
         outputs.push(output);
     }
 
-    let dex_script_dep = CellDep::new_builder().out_point(dex_out_point).build();
     let mut witnesses = vec![];
     for _ in 0..inputs.len() {
-        witnesses.push(Bytes::new())
+        witnesses.push(fill_witness())
     }
 
     // build transaction
@@ -133,12 +169,32 @@ fn build_test_context(
         .inputs(inputs)
         .outputs(outputs)
         .outputs_data(outputs_data.pack())
-        .cell_dep(dex_script_dep)
+        .cell_deps(cell_deps)
         .witnesses(witnesses.pack())
         .build();
     (context, tx)
 }
 
+fn sudt_data(sudt_amount: u128) -> Bytes {
+    Bytes::from(sudt_amount.to_le_bytes().to_vec())
+}
+
+fn order_data(
+    sudt_amount: u128,
+    dealt_amount: u128,
+    undealt_amount: u128,
+    price: u64,
+    order_type: u8,
+) -> Bytes {
+    let mut data = Vec::new();
+    data.extend_from_slice(&sudt_amount.to_le_bytes());
+    data.extend_from_slice(&dealt_amount.to_le_bytes());
+    data.extend_from_slice(&undealt_amount.to_le_bytes());
+    data.extend_from_slice(&price.to_le_bytes());
+    data.push(order_type);
+    Bytes::from(data)
+}
+
 #[test]
 // Assume the sudt decimal is 8 and the price 5 sudt/ckb
 fn test_ckb_sudt_partial_order() {
@@ -560,6 +616,2107 @@ fn test_ckb_sudt_all_order_cell_data_format_error() {
     );
 }
 
+fn order_data_v1(
+    sudt_amount: u128,
+    dealt_amount: u128,
+    undealt_amount: u128,
+    price: u64,
+    order_type: u8,
+    flags: u16,
+    fields: &[u8],
+) -> Bytes {
+    let mut data = Vec::new();
+    data.extend_from_slice(&sudt_amount.to_le_bytes());
+    data.push(1);
+    data.extend_from_slice(&flags.to_le_bytes());
+    data.extend_from_slice(&dealt_amount.to_le_bytes());
+    data.extend_from_slice(&undealt_amount.to_le_bytes());
+    data.extend_from_slice(&price.to_le_bytes());
+    data.push(order_type);
+    data.extend_from_slice(fields);
+    Bytes::from(data)
+}
+
+// The same matching as test_ckb_sudt_partial_order, with the order cells data given by the caller
+// buy: sudt_amount(50sudt) + dealt_amount(50sudt) + undealt_amount(150sudt) + price(5) -> sudt_amount(200sudt)
+// sell: sudt_amount(500sudt) + dealt_amount(100sudt) + undealt_amount(200sudt) + price(5)
+//    -> sudt_amount(349.55sudt) + dealt_amount(250sudt) + undealt_amount(50sudt)
+fn build_partial_order_context(
+    inputs_data: Vec<Bytes>,
+    outputs_data: Vec<Bytes>,
+) -> (Context, TransactionView) {
+    let inputs_args = vec![
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    let outputs_args = inputs_args.clone();
+    build_test_context(
+        vec![200000000000, 80000000000],
+        vec![124775000000, 155000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    )
+}
+
+#[test]
+fn test_ckb_sudt_v1_partial_order() {
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 0, &[]),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0, &[]),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0, &[]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_v0_order_to_v1_error() {
+    // the remaining v0 sell order is rewritten as v1
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0, &[]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(18).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_unknown_order_version() {
+    let mut sell_order_data =
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0, &[]).to_vec();
+    // version 2 is not defined
+    sell_order_data[16] = 2;
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 0, &[]),
+        Bytes::from(sell_order_data),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0, &[]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(18).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_unknown_order_flags() {
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 0, &[]),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x8000, &[]),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x8000, &[]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(19).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_zero_fee_rate_order() {
+    // both orders carry fee_rate(0), so no fee is paid for the matching
+    let zero_fee_rate = 0u16.to_le_bytes();
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 1, &zero_fee_rate),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 1, &zero_fee_rate),
+    ];
+    // output1: sudt_amount(200sudt)
+    // output2: sudt_amount(350sudt) + dealt_amount(250sudt) + undealt_amount(50sudt)
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(35000000000, 25000000000, 5000000000, 50000000000, 1, 1, &zero_fee_rate),
+    ];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    let outputs_args = inputs_args.clone();
+    // output1 capacity = 2000 - 750 = 1250
+    // output2 capacity = 800 + 750 = 1550
+    let (mut context, tx) = build_test_context(
+        vec![200000000000, 80000000000],
+        vec![125000000000, 155000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_fee_rate_exceeded() {
+    // the buyer only accepts fee_rate(10 = 0.1%) but is charged 0.3%
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 1, &10u16.to_le_bytes()),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(16).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_wrong_fee_rate() {
+    // fee_rate(10001) is more than 100%
+    let wrong_fee_rate = 10001u16.to_le_bytes();
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 1, &wrong_fee_rate),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 1, &wrong_fee_rate),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(21).input_lock_script(script_cell_index)
+    );
+}
+
+fn expiry_field(expiry_type: u8, expiry: u64) -> Vec<u8> {
+    let mut field = vec![expiry_type];
+    field.extend_from_slice(&expiry.to_le_bytes());
+    field
+}
+
+fn with_header_dep(
+    context: &mut Context,
+    tx: TransactionView,
+    number: u64,
+    timestamp: u64,
+) -> TransactionView {
+    let header = HeaderBuilder::default()
+        .number(number.pack())
+        .timestamp(timestamp.pack())
+        .build();
+    context.insert_header(header.clone());
+    tx.as_advanced_builder().header_dep(header.hash()).build()
+}
+
+#[test]
+fn test_ckb_sudt_order_before_expiry() {
+    // the buy order expires at block 1000
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 2, &expiry_field(0, 1000)),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = with_header_dep(&mut context, tx, 999, 0);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_order_fill_after_expiry() {
+    // the buy order expires at block 1000
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 2, &expiry_field(0, 1000)),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = with_header_dep(&mut context, tx, 1000, 0);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(24).input_lock_script(script_cell_index)
+    );
+}
+
+fn build_expired_order_context(output_args: Bytes) -> (Context, TransactionView) {
+    // the sell order expires at timestamp 1600000000000
+    let inputs_data = vec![order_data_v1(
+        50000000000,
+        10000000000,
+        20000000000,
+        50000000000,
+        1,
+        2,
+        &expiry_field(1, 1600000000000),
+    )];
+    let outputs_data = vec![sudt_data(50000000000)];
+    let inputs_args = vec![Bytes::from(
+        hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap(),
+    )];
+    let (mut context, tx) = build_test_context(
+        vec![80000000000],
+        vec![80000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        vec![output_args],
+    );
+    let tx = with_header_dep(&mut context, tx, 2000, 1600000000001);
+    (context, tx)
+}
+
+#[test]
+fn test_expired_order_return_without_signature() {
+    let (mut context, tx) = build_expired_order_context(Bytes::from(
+        hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap(),
+    ));
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_expired_order_return_to_other_lock() {
+    let (mut context, tx) = build_expired_order_context(Bytes::from(
+        hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap(),
+    ));
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(23).input_lock_script(script_cell_index)
+    );
+}
+
+fn replace_output_lock(tx: TransactionView, index: usize, lock: Script) -> TransactionView {
+    let outputs: Vec<CellOutput> = tx
+        .outputs()
+        .into_iter()
+        .enumerate()
+        .map(|(i, output)| {
+            if i == index {
+                output.as_builder().lock(lock.clone()).build()
+            } else {
+                output
+            }
+        })
+        .collect();
+    tx.as_advanced_builder().set_outputs(outputs).build()
+}
+
+fn matcher_lock() -> Script {
+    Script::new_builder()
+        .code_hash([1u8; 32].pack())
+        .args(Bytes::from(hex::decode("c6e3b3a3ae8e2c8e6a1e4fd43d0d7b6b1d6e5f2a").unwrap()).pack())
+        .build()
+}
+
+#[test]
+fn test_ckb_sudt_completed_order_to_other_lock() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    // the matcher takes the completed buy order
+    let tx = replace_output_lock(tx, 0, matcher_lock());
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(23).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_remaining_order_to_other_lock() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    // the matcher takes the remaining sell order together with its capacity
+    let tx = replace_output_lock(tx, 1, matcher_lock());
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(23).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_completed_order_to_owner_lock() {
+    // the buy order records the owner lock hash which receives the completed order
+    let owner_lock = Script::new_builder()
+        .code_hash([2u8; 32].pack())
+        .args(Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()).pack())
+        .build();
+    let owner_lock_hash = owner_lock.calc_script_hash();
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 4, owner_lock_hash.as_slice()),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = replace_output_lock(tx, 0, owner_lock);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_completed_order_not_to_owner_lock() {
+    // the buy order records an owner lock hash, but the completed order stays with the order lock
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 4, &[2u8; 32]),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(23).input_lock_script(script_cell_index)
+    );
+}
+
+fn molecule_table(fields: &[&[u8]]) -> Vec<u8> {
+    let header_size = 4 * (fields.len() + 1);
+    let total_size = header_size + fields.iter().map(|field| field.len()).sum::<usize>();
+    let mut table = Vec::new();
+    table.extend_from_slice(&(total_size as u32).to_le_bytes());
+    let mut offset = header_size;
+    for field in fields {
+        table.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+    for field in fields {
+        table.extend_from_slice(field);
+    }
+    table
+}
+
+// OrderAction union: item_id(u32) + item, 0 fill, 1 cancel, 2 modify
+fn order_action(item_id: u32, item: &[u8]) -> Bytes {
+    let mut action = item_id.to_le_bytes().to_vec();
+    action.extend_from_slice(item);
+    Bytes::from(action)
+}
+
+fn action_witness(item_id: u32, item: &[u8]) -> Bytes {
+    WitnessArgs::new_builder()
+        .output_type(Some(order_action(item_id, item)).pack())
+        .build()
+        .as_bytes()
+}
+
+// Fill without settlement, the order is settled into the output at the same index
+fn fill_witness() -> Bytes {
+    action_witness(0, &[])
+}
+
+fn cancel_witness() -> Bytes {
+    action_witness(1, &molecule_table(&[]))
+}
+
+fn modify_witness(output_index: u32) -> Bytes {
+    action_witness(2, &molecule_table(&[&output_index.to_le_bytes()]))
+}
+
+fn settlement_witness(output_index: u32) -> Bytes {
+    action_witness(0, &molecule_table(&[&output_index.to_le_bytes()]))
+}
+
+fn settlement_witness_with_side(output_index: u32, side: u8) -> Bytes {
+    action_witness(0, &molecule_table(&[&output_index.to_le_bytes(), &[side]]))
+}
+
+fn batch_witness(output_index: u32, clearing_price: u64) -> Bytes {
+    action_witness(
+        0,
+        &molecule_table(&[&output_index.to_le_bytes(), &[], &[], &clearing_price.to_le_bytes()]),
+    )
+}
+
+fn with_witnesses(tx: TransactionView, witnesses: Vec<Bytes>) -> TransactionView {
+    tx.as_advanced_builder()
+        .set_witnesses(witnesses.into_iter().map(|witness| witness.pack()).collect())
+        .build()
+}
+
+#[test]
+fn test_ckb_sudt_order_with_settlement_witness() {
+    // input1: the sell order of test_ckb_sudt_partial_order, settled into output3
+    // input2: the buy order of test_ckb_sudt_partial_order, settled into output2
+    let inputs_data = vec![
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+    ];
+    // output1: the fee cell of the matcher
+    let outputs_data = vec![
+        Bytes::new(),
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+    ];
+    let outputs_args = vec![
+        Bytes::from(hex::decode("c6e3b3a3ae8e2c8e6a1e4fd43d0d7b6b1d6e5f2a").unwrap()),
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    // output1 capacity = 2000 + 800 - 1247.75 - 1550 = 2.25
+    let (mut context, tx) = build_test_context(
+        vec![80000000000, 200000000000],
+        vec![225000000, 124775000000, 155000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    );
+    let tx = with_witnesses(tx, vec![settlement_witness(2), settlement_witness(1)]);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_orders_settled_into_same_output() {
+    // two identical sell orders of different owners both claim output1
+    let inputs_data = vec![
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![order_data(34955000000, 25000000000, 5000000000, 50000000000, 1)];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+    ];
+    let outputs_args = vec![Bytes::from(
+        hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap(),
+    )];
+    let (mut context, tx) = build_test_context(
+        vec![80000000000, 80000000000],
+        vec![155000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    );
+    let tx = with_witnesses(tx, vec![settlement_witness(0), settlement_witness(0)]);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(25).input_lock_script(script_cell_index)
+    );
+}
+
+// The sell order of test_ckb_sudt_partial_order deals 150sudt and leaves 50sudt undealt
+fn build_min_fill_order_context(min_fill_amount: u128) -> (Context, TransactionView) {
+    let min_fill_field = min_fill_amount.to_le_bytes();
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 8, &min_fill_field),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 8, &min_fill_field),
+    ];
+    build_partial_order_context(inputs_data, outputs_data)
+}
+
+#[test]
+fn test_ckb_sudt_order_min_fill() {
+    // min_fill_amount(50sudt)
+    let (mut context, tx) = build_min_fill_order_context(5000000000);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_order_fill_amount_too_small() {
+    // min_fill_amount(160sudt) is more than the dealt 150sudt
+    let (mut context, tx) = build_min_fill_order_context(16000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(27).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_order_remaining_amount_too_small() {
+    // min_fill_amount(60sudt) is more than the remaining 50sudt
+    let (mut context, tx) = build_min_fill_order_context(6000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(28).input_lock_script(script_cell_index)
+    );
+}
+
+// The matching of test_ckb_sudt_all_order2 with the order flags and fields given by the caller
+fn build_all_order_context(flags: u16, fields: &[u8]) -> (Context, TransactionView) {
+    let inputs_data = vec![
+        order_data_v1(0, 0, 15000000000, 50000000000, 0, flags, fields),
+        order_data_v1(50000000000, 0, 15000000000, 50000000000, 1, flags, fields),
+    ];
+    let outputs_data = vec![sudt_data(15000000000), sudt_data(34955000000)];
+    build_partial_order_context(inputs_data, outputs_data)
+}
+
+#[test]
+fn test_ckb_sudt_all_or_none_order() {
+    let (mut context, tx) = build_all_order_context(0x10, &[]);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_all_or_none_order_partially_filled() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x10, &[]),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x10, &[]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(29).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_fill_or_kill_order() {
+    // fill-or-kill with expiry at block 1000
+    let (mut context, tx) = build_all_order_context(0x22, &expiry_field(0, 1000));
+    let tx = with_header_dep(&mut context, tx, 999, 0);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_fill_or_kill_order_after_expiry_without_header_dep() {
+    // the expiry at block 1000 is only seen through header deps, so the order is still filled
+    // without them
+    let (mut context, tx) = build_all_order_context(0x22, &expiry_field(0, 1000));
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_fill_or_kill_order_partially_filled() {
+    let expiry = expiry_field(0, 1000);
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x22, &expiry),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x22, &expiry),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(29).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_fill_or_kill_order_without_expiry() {
+    let (mut context, tx) = build_all_order_context(0x20, &[]);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(19).input_lock_script(script_cell_index)
+    );
+}
+
+// The sell order of test_ckb_sudt_partial_order is post-only
+fn build_post_only_order_context() -> (Context, TransactionView) {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x40, &[]),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x40, &[]),
+    ];
+    build_partial_order_context(inputs_data, outputs_data)
+}
+
+#[test]
+fn test_ckb_sudt_post_only_order_as_maker() {
+    let (mut context, tx) = build_post_only_order_context();
+    // the buy order is taker(1) and the sell order is maker(0)
+    let tx = with_witnesses(
+        tx,
+        vec![settlement_witness_with_side(0, 1), settlement_witness_with_side(1, 0)],
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_post_only_order_as_taker() {
+    let (mut context, tx) = build_post_only_order_context();
+    let tx = with_witnesses(
+        tx,
+        vec![settlement_witness_with_side(0, 0), settlement_witness_with_side(1, 1)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(30).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_post_only_order_without_side() {
+    // without settlement witness nothing says the post-only order is maker
+    let (mut context, tx) = build_post_only_order_context();
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(30).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_post_only_order_against_maker() {
+    // both orders are settled as maker, so nothing takes the post-only order
+    let (mut context, tx) = build_post_only_order_context();
+    let tx = with_witnesses(
+        tx,
+        vec![settlement_witness_with_side(0, 0), settlement_witness_with_side(1, 0)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(66).input_lock_script(script_cell_index)
+    );
+}
+
+// The buy order of test_ckb_sudt_partial_order is a market order with the price bound given by the caller
+fn build_market_order_context(
+    price: u64,
+    flags: u16,
+    price_bound: u64,
+) -> (Context, TransactionView) {
+    let price_bound = price_bound.to_le_bytes();
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, price, 0, flags, &price_bound),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    build_partial_order_context(inputs_data, outputs_data)
+}
+
+#[test]
+fn test_ckb_sudt_market_order() {
+    let (mut context, tx) = build_market_order_context(0, 0x100, 50000000000);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_market_order_price_bound_exceeded() {
+    // the buyer pays 5 ckb/sudt, but accepts 4.9 ckb/sudt at most
+    let (mut context, tx) = build_market_order_context(0, 0x100, 49000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(16).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_market_order_with_price() {
+    let (mut context, tx) = build_market_order_context(50000000000, 0x100, 50000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(20).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_market_order_without_price_bound() {
+    let (mut context, tx) = build_market_order_context(0, 0x100, 0);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(14).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_post_only_market_order() {
+    let (mut context, tx) = build_market_order_context(0, 0x140, 50000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(19).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_order_id_kept() {
+    let order_id = [7u8; 32];
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x200, &order_id),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x200, &order_id),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_order_id_changed() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x200, &[7u8; 32]),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x200, &[8u8; 32]),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(32).input_lock_script(script_cell_index)
+    );
+}
+
+fn oracle_type() -> Script {
+    Script::new_builder()
+        .code_hash([2u8; 32].pack())
+        .args(Bytes::from(&b"ckb/sudt"[..]).pack())
+        .build()
+}
+
+fn trigger_field(trigger_price: u64, trigger_direction: u8) -> Vec<u8> {
+    let mut field = oracle_type().calc_script_hash().as_slice().to_vec();
+    field.extend_from_slice(&trigger_price.to_le_bytes());
+    field.push(trigger_direction);
+    field
+}
+
+// The mock oracle cell only carries the reference price
+fn with_oracle_dep(context: &mut Context, tx: TransactionView, price: u64) -> TransactionView {
+    let oracle_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(100000000000u64.pack())
+            .type_(Some(oracle_type()).pack())
+            .build(),
+        Bytes::from(price.to_le_bytes().to_vec()),
+    );
+    tx.as_advanced_builder()
+        .cell_dep(CellDep::new_builder().out_point(oracle_out_point).build())
+        .build()
+}
+
+// The sell order of test_ckb_sudt_partial_order is a stop-loss order at 5.5 ckb/sudt
+fn build_stop_loss_order_context() -> (Context, TransactionView) {
+    let trigger = trigger_field(55000000000, 0);
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x400, &trigger),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x400, &trigger),
+    ];
+    build_partial_order_context(inputs_data, outputs_data)
+}
+
+#[test]
+fn test_ckb_sudt_stop_loss_order_triggered() {
+    let (mut context, tx) = build_stop_loss_order_context();
+    let tx = with_oracle_dep(&mut context, tx, 54000000000);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_stop_loss_order_not_triggered() {
+    let (mut context, tx) = build_stop_loss_order_context();
+    let tx = with_oracle_dep(&mut context, tx, 56000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(35).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_stop_loss_order_without_oracle() {
+    let (mut context, tx) = build_stop_loss_order_context();
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(34).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_buy_stop_order_triggered() {
+    let trigger = trigger_field(45000000000, 1);
+    let inputs_data = vec![
+        order_data_v1(5000000000, 5000000000, 15000000000, 50000000000, 0, 0x400, &trigger),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = with_oracle_dep(&mut context, tx, 45000000000);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+// Some extension data of an xUDT cell after the amount
+fn udt_extension() -> Vec<u8> {
+    let mut extension = vec![0x10, 0, 0, 0, 0x0c, 0, 0, 0];
+    extension.extend_from_slice(&[0xaa; 8]);
+    extension
+}
+
+// The order keeps the udt extension right after the amount, and moves its region to the end
+// followed by the region length
+fn with_udt_extension(order: Bytes, extension: &[u8]) -> Bytes {
+    let region = &order[16..];
+    let mut data = order[..16].to_vec();
+    data.extend_from_slice(extension);
+    data.extend_from_slice(region);
+    data.extend_from_slice(&(region.len() as u32).to_le_bytes());
+    Bytes::from(data)
+}
+
+fn udt_extension_order(
+    sudt_amount: u128,
+    dealt_amount: u128,
+    undealt_amount: u128,
+    order_type: u8,
+) -> Bytes {
+    with_udt_extension(
+        order_data_v1(
+            sudt_amount,
+            dealt_amount,
+            undealt_amount,
+            50000000000,
+            order_type,
+            0x800,
+            &[],
+        ),
+        &udt_extension(),
+    )
+}
+
+fn sudt_data_with_extension(sudt_amount: u128, extension: &[u8]) -> Bytes {
+    let mut data = sudt_amount.to_le_bytes().to_vec();
+    data.extend_from_slice(extension);
+    Bytes::from(data)
+}
+
+#[test]
+fn test_xudt_partial_order() {
+    let extension = udt_extension();
+    let inputs_data = vec![
+        udt_extension_order(5000000000, 5000000000, 15000000000, 0),
+        udt_extension_order(50000000000, 10000000000, 20000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data_with_extension(20000000000, &extension),
+        udt_extension_order(34955000000, 25000000000, 5000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_xudt_completed_order_without_extension() {
+    let inputs_data = vec![
+        udt_extension_order(5000000000, 5000000000, 15000000000, 0),
+        udt_extension_order(50000000000, 10000000000, 20000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        udt_extension_order(34955000000, 25000000000, 5000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(36).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_xudt_remaining_order_extension_changed() {
+    let extension = udt_extension();
+    let mut changed_extension = extension.clone();
+    changed_extension[8] = 0xbb;
+    let inputs_data = vec![
+        udt_extension_order(5000000000, 5000000000, 15000000000, 0),
+        udt_extension_order(50000000000, 10000000000, 20000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data_with_extension(20000000000, &extension),
+        with_udt_extension(
+            order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x800, &[]),
+            &changed_extension,
+        ),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(36).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_udt_extension_order_with_sudt_type() {
+    // the order cells are of a real sudt type script, which reads the amount in front of the
+    // extension and keeps the total amount
+    let simple_udt_bin: Bytes = fs::read("../ckb-miscellaneous-scripts/build/simple_udt")
+        .expect("load simple_udt")
+        .into();
+    let extension = udt_extension();
+    let inputs_data = vec![
+        udt_extension_order(5000000000, 5000000000, 15000000000, 0),
+        udt_extension_order(50000000000, 10000000000, 20000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data_with_extension(20000000000, &extension),
+        udt_extension_order(34955000000, 25000000000, 5000000000, 1),
+    ];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    let (mut context, tx) = build_udt_test_context(
+        Some(simple_udt_bin),
+        vec![200000000000, 80000000000],
+        vec![124775000000, 155000000000],
+        inputs_data,
+        outputs_data,
+        inputs_args.clone(),
+        inputs_args,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_udt_extension_order_region_in_front() {
+    // the order region is put in front of the extension, where the udt reads its extension data
+    let extension = udt_extension();
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0x800, &extension),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0x800, &extension),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(9).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_order_trailing_data_without_extension_flag() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(50000000000, 10000000000, 20000000000, 50000000000, 1, 0, &udt_extension()),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(34955000000, 25000000000, 5000000000, 50000000000, 1, 0, &udt_extension()),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(9).input_lock_script(script_cell_index)
+    );
+}
+
+// A sell order of 200 sudt A quoted in sudt B at the price 5 sudt B/sudt A deals 150 sudt A,
+// and the 750 sudt B are paid into a new cell of the owner
+fn build_quote_order_context(
+    order_type: u8,
+    quote_output_type_args: Bytes,
+    quote_amount: u128,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let dex_bin: Bytes = Loader::default().load_binary("ckb-dex-contract");
+    let dex_out_point = context.deploy_cell(dex_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+
+    let owner_lock = context
+        .build_script(
+            &dex_out_point,
+            Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+        )
+        .expect("script");
+    let sudt_a_type = context
+        .build_script(&always_success_out_point, Bytes::from(vec![1]))
+        .expect("script");
+    let sudt_b_type = context
+        .build_script(&always_success_out_point, Bytes::from(vec![2]))
+        .expect("script");
+    let quote_output_type = context
+        .build_script(&always_success_out_point, quote_output_type_args)
+        .expect("script");
+    let quote_type_hash = sudt_b_type.calc_script_hash();
+
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(80000000000u64.pack())
+            .lock(owner_lock.clone())
+            .type_(Some(sudt_a_type.clone()).pack())
+            .build(),
+        order_data_v1(
+            50000000000,
+            10000000000,
+            20000000000,
+            50000000000,
+            order_type,
+            0x80,
+            quote_type_hash.as_slice(),
+        ),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+
+    let outputs = vec![
+        CellOutput::new_builder()
+            .capacity(80000000000u64.pack())
+            .lock(owner_lock.clone())
+            .type_(Some(sudt_a_type).pack())
+            .build(),
+        CellOutput::new_builder()
+            .capacity(14200000000u64.pack())
+            .lock(owner_lock)
+            .type_(Some(quote_output_type).pack())
+            .build(),
+    ];
+    let outputs_data = vec![
+        order_data_v1(
+            34955000000,
+            25000000000,
+            5000000000,
+            50000000000,
+            order_type,
+            0x80,
+            quote_type_hash.as_slice(),
+        ),
+        sudt_data(quote_amount),
+    ];
+    // the remaining order is output1 and the quote udt is paid into output2
+    let settlement = molecule_table(&[&0u32.to_le_bytes(), &[], &1u32.to_le_bytes()]);
+    let witness = action_witness(0, &settlement);
+
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .outputs(outputs)
+        .outputs_data(outputs_data.pack())
+        .cell_dep(CellDep::new_builder().out_point(dex_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point)
+                .build(),
+        )
+        .witness(witness.pack())
+        .build();
+    (context, tx)
+}
+
+#[test]
+fn test_sudt_sudt_order() {
+    let (mut context, tx) = build_quote_order_context(1, Bytes::from(vec![2]), 75000000000);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_sudt_sudt_order_in_batch() {
+    // a batch can't conserve the quote udt paid into output2
+    let (mut context, tx) = build_quote_order_context(1, Bytes::from(vec![2]), 75000000000);
+    let settlement = molecule_table(&[
+        &0u32.to_le_bytes(),
+        &[],
+        &1u32.to_le_bytes(),
+        &50000000000u64.to_le_bytes(),
+    ]);
+    let tx = with_witnesses(tx, vec![action_witness(0, &settlement)]);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(41).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_sudt_sudt_order_quote_amount_error() {
+    let (mut context, tx) = build_quote_order_context(1, Bytes::from(vec![2]), 74999999999);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(16).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_sudt_sudt_order_quote_type_error() {
+    // the order is paid with sudt A instead of sudt B
+    let (mut context, tx) = build_quote_order_context(1, Bytes::from(vec![1]), 75000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(31).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_sudt_sudt_buy_order_error() {
+    let (mut context, tx) = build_quote_order_context(0, Bytes::from(vec![2]), 75000000000);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(13).input_lock_script(script_cell_index)
+    );
+}
+
+// A sell order of a market which only accepts the udt of type args market_udt_args, or a legacy
+// order of the bare pubkey hash without it
+fn build_market_udt_order_context(
+    udt_args: u8,
+    market_udt_args: Option<u8>,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let dex_bin: Bytes = Loader::default().load_binary("ckb-dex-contract");
+    let dex_out_point = context.deploy_cell(dex_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+
+    let udt_type = context
+        .build_script(&always_success_out_point, Bytes::from(vec![udt_args]))
+        .expect("script");
+    // pubkey_hash + the accepted udt type hash
+    let mut args = hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap();
+    if let Some(market_udt_args) = market_udt_args {
+        let market_udt_type = context
+            .build_script(&always_success_out_point, Bytes::from(vec![market_udt_args]))
+            .expect("script");
+        args.extend_from_slice(market_udt_type.calc_script_hash().as_slice());
+    }
+    let owner_lock = context
+        .build_script(&dex_out_point, Bytes::from(args))
+        .expect("script");
+
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(80000000000u64.pack())
+            .lock(owner_lock.clone())
+            .type_(Some(udt_type.clone()).pack())
+            .build(),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+    let output = CellOutput::new_builder()
+        .capacity(155000000000u64.pack())
+        .lock(owner_lock)
+        .type_(Some(udt_type).pack())
+        .build();
+
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(order_data(34955000000, 25000000000, 5000000000, 50000000000, 1).pack())
+        .cell_dep(CellDep::new_builder().out_point(dex_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point)
+                .build(),
+        )
+        .witness(fill_witness().pack())
+        .build();
+    (context, tx)
+}
+
+#[test]
+fn test_market_udt_order() {
+    let (mut context, tx) = build_market_udt_order_context(1, Some(1));
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_market_udt_order_on_other_udt() {
+    let (mut context, tx) = build_market_udt_order_context(2, Some(1));
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(37).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_legacy_order_without_market_udt() {
+    // an order of the bare pubkey hash is filled on any udt as before
+    let (mut context, tx) = build_market_udt_order_context(2, None);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_order_without_action() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = with_witnesses(tx, vec![Bytes::new(), Bytes::new()]);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(38).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_order_with_unknown_action() {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 50000000000, 1),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+    let tx = with_witnesses(tx, vec![action_witness(3, &[]), fill_witness()]);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(39).input_lock_script(script_cell_index)
+    );
+}
+
+// The owner spends the sell order of test_ckb_sudt_partial_order with the action given by the caller
+fn build_owner_order_context(
+    privkey: &Privkey,
+    output_data: Bytes,
+    witness: Bytes,
+) -> (Context, TransactionView) {
+    let pubkey = privkey.pubkey().expect("pubkey");
+    let pubkey_hash = blake160(&pubkey.serialize());
+
+    let mut context = Context::default();
+    let contract_bin: Bytes = Loader::default().load_binary("ckb-dex-contract");
+    let out_point = context.deploy_cell(contract_bin);
+
+    let secp256k1_bin: Bytes =
+        fs::read("../ckb-miscellaneous-scripts/build/secp256k1_blake2b_sighash_all_dual")
+            .expect("load secp256k1")
+            .into();
+    let secp256k1_out_point = context.deploy_cell(secp256k1_bin);
+    let secp256k1_data_bin = BUNDLED_CELL.get("specs/cells/secp256k1_data").unwrap();
+    let secp256k1_data_out_point = context.deploy_cell(secp256k1_data_bin.to_vec().into());
+
+    let lock_script = context
+        .build_script(&out_point, pubkey_hash.to_vec().into())
+        .expect("script");
+    let input_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(80000000000u64.pack())
+            .lock(lock_script.clone())
+            .build(),
+        order_data(50000000000, 10000000000, 20000000000, 50000000000, 1),
+    );
+    let input = CellInput::new_builder()
+        .previous_output(input_out_point)
+        .build();
+    let output = CellOutput::new_builder()
+        .capacity(80000000000u64.pack())
+        .lock(lock_script)
+        .build();
+
+    let tx = TransactionBuilder::default()
+        .input(input)
+        .output(output)
+        .output_data(output_data.pack())
+        .cell_dep(CellDep::new_builder().out_point(out_point).build())
+        .cell_dep(CellDep::new_builder().out_point(secp256k1_out_point).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(secp256k1_data_out_point)
+                .build(),
+        )
+        .witness(witness.pack())
+        .build();
+    let tx = context.complete_tx(tx);
+    let tx = sign_tx(tx, privkey);
+    (context, tx)
+}
+
+#[test]
+fn test_cancel_order() {
+    let privkey = Generator::random_privkey();
+    let (mut context, tx) =
+        build_owner_order_context(&privkey, sudt_data(50000000000), cancel_witness());
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_modify_order_price() {
+    let privkey = Generator::random_privkey();
+    // the price is changed to 6
+    let output_data = order_data(50000000000, 10000000000, 20000000000, 60000000000, 1);
+    let (mut context, tx) = build_owner_order_context(&privkey, output_data, modify_witness(0));
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_modify_order_sudt_amount() {
+    let privkey = Generator::random_privkey();
+    let output_data = order_data(40000000000, 10000000000, 20000000000, 60000000000, 1);
+    let (mut context, tx) = build_owner_order_context(&privkey, output_data, modify_witness(0));
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(40).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_modify_order_with_wrong_key() {
+    let privkey = Generator::random_privkey();
+    let output_data = order_data(50000000000, 10000000000, 20000000000, 60000000000, 1);
+    let (mut context, tx) = build_owner_order_context(&privkey, output_data, modify_witness(0));
+    let tx = sign_tx(tx, &Generator::random_privkey());
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(6).input_lock_script(script_cell_index)
+    );
+}
+
+// The buyer bids 5.5 ckb/sudt and the seller asks 4.5 ckb/sudt, and both are settled at 5 ckb/sudt
+// like test_ckb_sudt_partial_order, the seller receives output2_capacity
+fn build_batch_order_context(output2_capacity: u64) -> (Context, TransactionView) {
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 55000000000, 0),
+        order_data(50000000000, 10000000000, 20000000000, 45000000000, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data(34955000000, 25000000000, 5000000000, 45000000000, 1),
+    ];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    let outputs_args = inputs_args.clone();
+    build_test_context(
+        vec![200000000000, 80000000000],
+        vec![124775000000, output2_capacity],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    )
+}
+
+#[test]
+fn test_ckb_sudt_batch_orders() {
+    let (mut context, tx) = build_batch_order_context(155000000000);
+    let tx = with_witnesses(
+        tx,
+        vec![batch_witness(0, 50000000000), batch_witness(1, 50000000000)],
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_batch_clearing_price_above_bid() {
+    let (mut context, tx) = build_batch_order_context(155000000000);
+    let tx = with_witnesses(
+        tx,
+        vec![batch_witness(0, 60000000000), batch_witness(1, 60000000000)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(41).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_batch_clearing_price_not_same() {
+    let (mut context, tx) = build_batch_order_context(155000000000);
+    let tx = with_witnesses(
+        tx,
+        vec![batch_witness(0, 50000000000), batch_witness(1, 49000000000)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(42).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_batch_capacity_not_conserved() {
+    // the seller receives 1600 ckb while the buyer only pays 752.25 ckb
+    let (mut context, tx) = build_batch_order_context(160000000000);
+    let tx = with_witnesses(
+        tx,
+        vec![batch_witness(0, 50000000000), batch_witness(1, 50000000000)],
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(43).input_lock_script(script_cell_index)
+    );
+}
+
+// The batch of build_batch_order_context, where the buyer trades the udt of type args buyer_udt_args
+// and the seller the one of seller_udt_args
+fn build_udt_batch_order_context(
+    buyer_udt_args: u8,
+    seller_udt_args: u8,
+) -> (Context, TransactionView) {
+    let mut context = Context::default();
+    let dex_bin: Bytes = Loader::default().load_binary("ckb-dex-contract");
+    let dex_out_point = context.deploy_cell(dex_bin);
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+
+    let orders = vec![
+        (
+            "7e7a30e75685e4d332f69220e925575dd9b84676",
+            buyer_udt_args,
+            (200000000000u64, 124775000000u64),
+            order_data(5000000000, 5000000000, 15000000000, 55000000000, 0),
+            sudt_data(20000000000),
+        ),
+        (
+            "a53ce751e2adb698ca10f8c1b8ebbee20d41a842",
+            seller_udt_args,
+            (80000000000u64, 155000000000u64),
+            order_data(50000000000, 10000000000, 20000000000, 45000000000, 1),
+            order_data(34955000000, 25000000000, 5000000000, 45000000000, 1),
+        ),
+    ];
+    let mut tx = TransactionBuilder::default()
+        .cell_dep(CellDep::new_builder().out_point(dex_out_point.clone()).build())
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point.clone())
+                .build(),
+        );
+    for (index, (args, udt_args, capacities, input_data, output_data)) in
+        orders.into_iter().enumerate()
+    {
+        let lock = context
+            .build_script(&dex_out_point, Bytes::from(hex::decode(args).unwrap()))
+            .expect("script");
+        let udt_type = context
+            .build_script(&always_success_out_point, Bytes::from(vec![udt_args]))
+            .expect("script");
+        let input_out_point = context.create_cell(
+            CellOutput::new_builder()
+                .capacity(capacities.0.pack())
+                .lock(lock.clone())
+                .type_(Some(udt_type.clone()).pack())
+                .build(),
+            input_data,
+        );
+        tx = tx
+            .input(
+                CellInput::new_builder()
+                    .previous_output(input_out_point)
+                    .build(),
+            )
+            .output(
+                CellOutput::new_builder()
+                    .capacity(capacities.1.pack())
+                    .lock(lock)
+                    .type_(Some(udt_type).pack())
+                    .build(),
+            )
+            .output_data(output_data.pack())
+            .witness(batch_witness(index as u32, 50000000000).pack());
+    }
+    (context, tx.build())
+}
+
+#[test]
+fn test_udt_batch_orders() {
+    let (mut context, tx) = build_udt_batch_order_context(1, 1);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_batch_orders_of_different_udts() {
+    // the seller's udt can't pay for the buyer's
+    let (mut context, tx) = build_udt_batch_order_context(1, 2);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(67).input_lock_script(script_cell_index)
+    );
+}
+
+fn matcher_config_type() -> Script {
+    Script::new_builder()
+        .code_hash([3u8; 32].pack())
+        .args(Bytes::from(&b"matchers"[..]).pack())
+        .build()
+}
+
+// The sell order of test_ckb_sudt_partial_order can only be filled by the listed matchers,
+// and an input of the matcher is added to the transaction
+fn build_matcher_set_order_context(authorised: bool, with_config: bool) -> (Context, TransactionView) {
+    let config_type_hash = matcher_config_type().calc_script_hash();
+    let inputs_data = vec![
+        order_data(5000000000, 5000000000, 15000000000, 50000000000, 0),
+        order_data_v1(
+            50000000000,
+            10000000000,
+            20000000000,
+            50000000000,
+            1,
+            0x1000,
+            config_type_hash.as_slice(),
+        ),
+    ];
+    let outputs_data = vec![
+        sudt_data(20000000000),
+        order_data_v1(
+            34955000000,
+            25000000000,
+            5000000000,
+            50000000000,
+            1,
+            0x1000,
+            config_type_hash.as_slice(),
+        ),
+    ];
+    let (mut context, tx) = build_partial_order_context(inputs_data, outputs_data);
+
+    let always_success_out_point = context.deploy_cell(ALWAYS_SUCCESS.clone());
+    let matcher_lock = context
+        .build_script(&always_success_out_point, Bytes::from(vec![1]))
+        .expect("script");
+    let matcher_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(10000000000u64.pack())
+            .lock(matcher_lock.clone())
+            .build(),
+        Bytes::new(),
+    );
+    let tx = tx
+        .as_advanced_builder()
+        .input(
+            CellInput::new_builder()
+                .previous_output(matcher_out_point)
+                .build(),
+        )
+        .cell_dep(
+            CellDep::new_builder()
+                .out_point(always_success_out_point)
+                .build(),
+        )
+        .build();
+    let tx = with_witnesses(tx, vec![settlement_witness(0), settlement_witness(1)]);
+    if !with_config {
+        return (context, tx);
+    }
+
+    let mut matchers = [9u8; 32].to_vec();
+    if authorised {
+        matchers.extend_from_slice(matcher_lock.calc_script_hash().as_slice());
+    }
+    let config_out_point = context.create_cell(
+        CellOutput::new_builder()
+            .capacity(100000000000u64.pack())
+            .type_(Some(matcher_config_type()).pack())
+            .build(),
+        Bytes::from(matchers),
+    );
+    let tx = tx
+        .as_advanced_builder()
+        .cell_dep(CellDep::new_builder().out_point(config_out_point).build())
+        .build();
+    (context, tx)
+}
+
+#[test]
+fn test_ckb_sudt_order_filled_by_authorised_matcher() {
+    let (mut context, tx) = build_matcher_set_order_context(true, true);
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_order_filled_by_unauthorised_matcher() {
+    let (mut context, tx) = build_matcher_set_order_context(false, true);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(46).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_order_without_matcher_config() {
+    let (mut context, tx) = build_matcher_set_order_context(true, false);
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(45).input_lock_script(script_cell_index)
+    );
+}
+
+// The amounts are far beyond 2^53, the comparisons must be exact rather than float approximations
+// buy: undealt_amount(10^27 + 7) and price(1, 10^-10 ckb/sudt)
+// max paid capacity = floor((10^27 + 7) * 1.003 / 10^10) = 100300000000000000
+const LARGE_UNDEALT_AMOUNT: u128 = 1_000_000_000_000_000_000_000_000_007;
+const LARGE_BUY_PAID_CAPACITY: u64 = 100_300_000_000_000_000;
+// sell: sudt_amount(u128::MAX) and price(1, 10^-10 ckb/sudt)
+// max spent sudt = floor((10^27 + 7) * 1.003) = 1003000000000000000000000007
+// min received capacity = ceil(1003000000000000000000000007 / 1.003 / 10^10) = 100000000000000001
+const LARGE_SELL_SPENT_AMOUNT: u128 = 1_003_000_000_000_000_000_000_000_007;
+const LARGE_SELL_RECEIVED_CAPACITY: u64 = 100_000_000_000_000_001;
+
+fn build_large_amount_context(
+    buyer_paid_capacity: u64,
+    seller_spent_amount: u128,
+    seller_received_capacity: u64,
+) -> (Context, TransactionView) {
+    let inputs_data = vec![
+        order_data(0, 0, LARGE_UNDEALT_AMOUNT, 1, 0),
+        order_data(u128::max_value(), 0, LARGE_UNDEALT_AMOUNT, 1, 1),
+    ];
+    let outputs_data = vec![
+        sudt_data(LARGE_UNDEALT_AMOUNT),
+        sudt_data(u128::max_value() - seller_spent_amount),
+    ];
+    let inputs_args = vec![
+        Bytes::from(hex::decode("7e7a30e75685e4d332f69220e925575dd9b84676").unwrap()),
+        Bytes::from(hex::decode("a53ce751e2adb698ca10f8c1b8ebbee20d41a842").unwrap()),
+    ];
+    let outputs_args = inputs_args.clone();
+    let (context, tx) = build_test_context(
+        vec![200000000000000000, 100000000000000000],
+        vec![
+            200000000000000000 - buyer_paid_capacity,
+            100000000000000000 + seller_received_capacity,
+        ],
+        inputs_data,
+        outputs_data,
+        inputs_args,
+        outputs_args,
+    );
+    (context, tx)
+}
+
+#[test]
+fn test_ckb_sudt_large_amount_order() {
+    let (mut context, tx) = build_large_amount_context(
+        LARGE_BUY_PAID_CAPACITY,
+        LARGE_SELL_SPENT_AMOUNT,
+        LARGE_SELL_RECEIVED_CAPACITY,
+    );
+    let tx = context.complete_tx(tx);
+
+    // run
+    let cycles = context
+        .verify_tx(&tx, MAX_CYCLES)
+        .expect("pass verification");
+    println!("cycles: {}", cycles);
+}
+
+#[test]
+fn test_ckb_sudt_large_amount_buy_overpaid() {
+    // the buyer pays one shannon more than the limit
+    let (mut context, tx) = build_large_amount_context(
+        LARGE_BUY_PAID_CAPACITY + 1,
+        LARGE_SELL_SPENT_AMOUNT,
+        LARGE_SELL_RECEIVED_CAPACITY,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 0;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(16).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_large_amount_sell_overspent() {
+    // the seller spends one sudt more than the limit
+    let (mut context, tx) = build_large_amount_context(
+        LARGE_BUY_PAID_CAPACITY,
+        LARGE_SELL_SPENT_AMOUNT + 1,
+        LARGE_SELL_RECEIVED_CAPACITY,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(10).input_lock_script(script_cell_index)
+    );
+}
+
+#[test]
+fn test_ckb_sudt_large_amount_sell_underpaid() {
+    // the seller receives one shannon less than the limit
+    let (mut context, tx) = build_large_amount_context(
+        LARGE_BUY_PAID_CAPACITY,
+        LARGE_SELL_SPENT_AMOUNT,
+        LARGE_SELL_RECEIVED_CAPACITY - 1,
+    );
+    let tx = context.complete_tx(tx);
+
+    let err = context.verify_tx(&tx, MAX_CYCLES).unwrap_err();
+    let script_cell_index = 1;
+    assert_error_eq!(
+        err,
+        ScriptError::ValidationFailure(16).input_lock_script(script_cell_index)
+    );
+}
+
 #[test]
 fn test_signature_basic() {
     // generate key pair
@@ -625,6 +2782,7 @@ fn test_signature_basic() {
         .cell_dep(lock_script_dep)
         .cell_dep(secp256k1_dep)
         .cell_dep(secp256k1_data_dep)
+        .witness(cancel_witness().pack())
         .build();
     let tx = context.complete_tx(tx);
 
@@ -704,6 +2862,7 @@ fn test_sign_with_wrong_key() {
         .cell_dep(lock_script_dep)
         .cell_dep(secp256k1_dep)
         .cell_dep(secp256k1_data_dep)
+        .witness(cancel_witness().pack())
         .build();
     let tx = context.complete_tx(tx);
 
@@ -788,6 +2947,7 @@ fn test_recover_pubkey() {
         args.extend_from_slice(&sig.serialize());
         WitnessArgs::new_builder()
             .input_type(Some(Bytes::from(args)).pack())
+            .output_type(Some(order_action(1, &molecule_table(&[]))).pack())
             .build()
             .as_bytes()
             .pack()
@@ -882,6 +3042,7 @@ fn test_recover_pubkey_with_wrong_signature() {
         args.extend_from_slice(&sig.serialize());
         WitnessArgs::new_builder()
             .input_type(Some(Bytes::from(args)).pack())
+            .output_type(Some(order_action(1, &molecule_table(&[]))).pack())
             .build()
             .as_bytes()
             .pack()